@@ -0,0 +1,95 @@
+use std::rc::Rc;
+
+use crate::{
+    parser::{BinaryOperator, Function, Litteral, UnaryOperator},
+    position::Position,
+};
+
+/// A single VM instruction. Jump targets are absolute indices into
+/// `Chunk::code`; name operands index into `Chunk::constants`.
+#[derive(Clone, Debug)]
+pub enum OpCode {
+    Constant(usize),
+    Nil,
+    Pop,
+    Print,
+    Define(usize),
+    Get(usize, Option<usize>),
+    Set(usize, Option<usize>),
+    Binary(BinaryOperator),
+    Unary(UnaryOperator),
+    Jump(usize),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    PushScope,
+    PopScope,
+    Closure(usize),
+    Call(usize),
+    Return,
+    Class(usize),
+    GetProperty(usize),
+    SetProperty(usize),
+    This,
+    GetSuper(usize),
+    /// Pops this many values and pushes them joined as one string.
+    Interpolate(usize),
+}
+
+/// A function body lowered to bytecode, kept next to its declaration so
+/// runtime values can share `Closure` with the tree-walking interpreter.
+pub struct CompiledFunction {
+    pub declaration: Rc<Function>,
+    pub chunk: Rc<Chunk>,
+}
+
+/// A class declaration. The superclass is looked up by the `Class`
+/// instruction itself, as a name plus its resolved depth, and errors about
+/// it are reported at its position.
+pub struct CompiledClass {
+    pub name: String,
+    pub superclass: Option<(String, Option<usize>, Position)>,
+    pub methods: Vec<usize>,
+}
+
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    /// Source span of each instruction, for diagnostics and disassembly.
+    pub positions: Vec<Position>,
+    pub constants: Vec<Litteral>,
+    pub functions: Vec<CompiledFunction>,
+    pub classes: Vec<CompiledClass>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Appends `op` and returns its offset.
+    pub fn write(&mut self, op: OpCode, position: Position) -> usize {
+        self.code.push(op);
+        self.positions.push(position);
+        self.code.len() - 1
+    }
+    pub fn add_constant(&mut self, value: Litteral) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+    /// Returns the constant slot holding the name `name`, reusing an
+    /// existing one when possible.
+    pub fn identifier(&mut self, name: &str) -> usize {
+        let existing = self.constants.iter().position(|c| {
+            matches!(c, Litteral::String(s) if s == name)
+        });
+        match existing {
+            Some(index) => index,
+            None => self.add_constant(Litteral::String(name.to_string())),
+        }
+    }
+    pub fn name(&self, index: usize) -> &String {
+        match &self.constants[index] {
+            Litteral::String(name) => name,
+            _ => unreachable!("name operand does not point at a string constant"),
+        }
+    }
+}
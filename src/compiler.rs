@@ -0,0 +1,268 @@
+use std::rc::Rc;
+
+use crate::{
+    chunk::{Chunk, CompiledClass, CompiledFunction, OpCode},
+    parser::{BinaryOperator, Function, Litteral, Node, Statement},
+    position::Position,
+};
+
+/// Lowers resolved statements into a `Chunk` for the VM. Scopes are
+/// mirrored one-to-one with the tree-walking interpreter so the depths
+/// computed by the resolver stay valid.
+pub struct Compiler {
+    chunk: Chunk,
+    position: Position,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            chunk: Chunk::new(),
+            position: Position::default(),
+        }
+    }
+    pub fn compile(statements: &[Statement]) -> Chunk {
+        let mut compiler = Compiler::new();
+        for stmt in statements {
+            compiler.statement(stmt);
+        }
+        compiler.chunk
+    }
+    fn function(function: &Function) -> CompiledFunction {
+        let mut compiler = Compiler::new();
+        compiler.position = function.position.clone();
+        for stmt in &function.body {
+            compiler.statement(stmt);
+        }
+        // The implicit `return nil` belongs to the declaration, not to
+        // whatever statement happened to be compiled last.
+        compiler.position = function.position.clone();
+        compiler.emit(OpCode::Nil);
+        compiler.emit(OpCode::Return);
+        CompiledFunction {
+            declaration: Rc::new(function.clone()),
+            chunk: Rc::new(compiler.chunk),
+        }
+    }
+    fn emit(&mut self, op: OpCode) -> usize {
+        self.chunk.write(op, self.position.clone())
+    }
+    /// Points the jump at `offset` to the next instruction to be emitted.
+    fn patch(&mut self, offset: usize) {
+        let target = self.chunk.code.len();
+        match &mut self.chunk.code[offset] {
+            OpCode::Jump(t) | OpCode::JumpIfFalse(t) | OpCode::JumpIfTrue(t) => *t = target,
+            _ => unreachable!(),
+        }
+    }
+    /// Makes the next ops report `position`, when there is one.
+    fn at(&mut self, position: Option<Position>) {
+        if let Some(position) = position {
+            self.position = position;
+        }
+    }
+    fn statement(&mut self, statement: &Statement) {
+        // Ops emitted after a nested body are attributed back to the
+        // statement owning them.
+        let position = statement.position();
+        self.at(position.clone());
+        match statement {
+            Statement::Expression(expr) => {
+                self.expression(expr);
+                self.emit(OpCode::Pop);
+            },
+            Statement::Print(expr) => {
+                self.expression(expr);
+                self.emit(OpCode::Print);
+            },
+            Statement::VarDecl(name, expr, _) => {
+                self.expression(expr);
+                let name = self.chunk.identifier(name);
+                self.emit(OpCode::Define(name));
+            },
+            Statement::Block(statements) => {
+                self.emit(OpCode::PushScope);
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+                self.at(position);
+                self.emit(OpCode::PopScope);
+            },
+            Statement::If(condition, then, els) => {
+                self.expression(condition);
+                let to_else = self.emit(OpCode::JumpIfFalse(0));
+                self.emit(OpCode::Pop);
+                self.statement(then);
+                self.at(position);
+                let to_end = self.emit(OpCode::Jump(0));
+                self.patch(to_else);
+                self.emit(OpCode::Pop);
+                if let Some(els) = els {
+                    self.statement(els);
+                }
+                self.patch(to_end);
+            },
+            Statement::While(condition, body) => {
+                let start = self.chunk.code.len();
+                self.expression(condition);
+                let to_exit = self.emit(OpCode::JumpIfFalse(0));
+                self.emit(OpCode::Pop);
+                self.statement(body);
+                self.at(position);
+                self.emit(OpCode::Jump(start));
+                self.patch(to_exit);
+                self.emit(OpCode::Pop);
+            },
+            Statement::For(init, con, inc, body) => {
+                self.emit(OpCode::PushScope);
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                let start = self.chunk.code.len();
+                let to_exit = con.as_ref().map(|con| {
+                    self.expression(con);
+                    let to_exit = self.emit(OpCode::JumpIfFalse(0));
+                    self.emit(OpCode::Pop);
+                    to_exit
+                });
+                self.statement(body);
+                if let Some(inc) = inc {
+                    self.expression(inc);
+                    self.emit(OpCode::Pop);
+                }
+                self.at(position);
+                self.emit(OpCode::Jump(start));
+                if let Some(to_exit) = to_exit {
+                    self.patch(to_exit);
+                    self.emit(OpCode::Pop);
+                }
+                self.emit(OpCode::PopScope);
+            },
+            Statement::Function(function) => {
+                self.position = function.position.clone();
+                let compiled = Compiler::function(function);
+                self.chunk.functions.push(compiled);
+                self.emit(OpCode::Closure(self.chunk.functions.len() - 1));
+                let name = self.chunk.identifier(&function.name);
+                self.emit(OpCode::Define(name));
+            },
+            Statement::Return(value, position) => {
+                match value {
+                    Some(value) => self.expression(value),
+                    None => {
+                        self.position = position.clone();
+                        self.emit(OpCode::Nil);
+                    },
+                }
+                self.emit(OpCode::Return);
+            },
+            Statement::Class(name, superclass, methods, position) => {
+                self.position = position.clone();
+                let superclass = match superclass {
+                    Some(Node::Identifier(parent, depth, position)) => Some((parent.clone(), *depth, position.clone())),
+                    _ => None,
+                };
+                let methods = methods.iter().map(|method| {
+                    self.chunk.functions.push(Compiler::function(method));
+                    self.chunk.functions.len() - 1
+                }).collect();
+                self.chunk.classes.push(CompiledClass {
+                    name: name.clone(),
+                    superclass,
+                    methods,
+                });
+                self.emit(OpCode::Class(self.chunk.classes.len() - 1));
+                let name = self.chunk.identifier(name);
+                self.emit(OpCode::Define(name));
+            },
+        }
+    }
+    fn expression(&mut self, expr: &Node) {
+        self.position = expr.position();
+        match expr {
+            Node::Binary { left, right, operator: BinaryOperator::Or, .. } => {
+                self.expression(left);
+                let to_end = self.emit(OpCode::JumpIfTrue(0));
+                self.emit(OpCode::Pop);
+                self.expression(right);
+                self.patch(to_end);
+            },
+            Node::Binary { left, right, operator: BinaryOperator::And, .. } => {
+                self.expression(left);
+                let to_end = self.emit(OpCode::JumpIfFalse(0));
+                self.emit(OpCode::Pop);
+                self.expression(right);
+                self.patch(to_end);
+            },
+            Node::Binary { left, right, operator, position } => {
+                self.expression(left);
+                self.expression(right);
+                self.position = position.clone();
+                self.emit(OpCode::Binary(operator.clone()));
+            },
+            Node::Parenthesis(inner) => self.expression(inner),
+            Node::Unary(op, inner, position) => {
+                self.expression(inner);
+                self.position = position.clone();
+                self.emit(OpCode::Unary(op.clone()));
+            },
+            Node::Litteral(Litteral::Nil, _) => {
+                self.emit(OpCode::Nil);
+            },
+            Node::Litteral(lit, _) => {
+                let constant = self.chunk.add_constant(lit.clone());
+                self.emit(OpCode::Constant(constant));
+            },
+            Node::Identifier(name, depth, _) => {
+                let name = self.chunk.identifier(name);
+                self.emit(OpCode::Get(name, *depth));
+            },
+            Node::Assignment(name, value, depth, position) => {
+                self.expression(value);
+                self.position = position.clone();
+                let name = self.chunk.identifier(name);
+                self.emit(OpCode::Set(name, *depth));
+            },
+            Node::Call { callee, arguments, position } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.position = position.clone();
+                self.emit(OpCode::Call(arguments.len()));
+            },
+            Node::Get(object, name, position) => {
+                self.expression(object);
+                self.position = position.clone();
+                let name = self.chunk.identifier(name);
+                self.emit(OpCode::GetProperty(name));
+            },
+            Node::Set(object, name, value, position) => {
+                self.expression(object);
+                self.expression(value);
+                self.position = position.clone();
+                let name = self.chunk.identifier(name);
+                self.emit(OpCode::SetProperty(name));
+            },
+            Node::This(_) => {
+                self.emit(OpCode::This);
+            },
+            Node::Super(method, _) => {
+                let method = self.chunk.identifier(method);
+                self.emit(OpCode::GetSuper(method));
+            },
+            Node::Interpolation(parts, _) => {
+                for part in parts {
+                    self.expression(part);
+                }
+                self.emit(OpCode::Interpolate(parts.len()));
+            },
+        }
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
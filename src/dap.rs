@@ -0,0 +1,478 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    io::{BufRead, Write},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    compile_file,
+    debugger::{lookup, scope_name, Location, Mode, Quit},
+    environment::Scope,
+    error::LoxError,
+    interpreter::{DebugHook, Interpreter},
+    parser::{Function, Statement},
+    position::Position,
+    protocol::{read_message, write_message},
+    source_map::{SourceFile, SourceMap},
+};
+
+/// The only thread a Lox program has.
+const THREAD_ID: i64 = 1;
+
+/// What the request loop should do after handling a request.
+enum Flow {
+    Wait,
+    Resume,
+    Launch,
+    Disconnect,
+}
+
+/// A Lox call in progress: the function and where it was called from.
+struct Call {
+    name: String,
+    position: Position,
+    /// The caller's scope at the call.
+    environment: Scope,
+}
+
+/// Debug Adapter Protocol session over a pair of streams. The program runs
+/// on the same thread: while it is paused, the interpreter hook reads and
+/// answers requests until one resumes execution.
+struct Session {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: i64,
+    program: Option<PathBuf>,
+    source: SourceFile,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    location: Location,
+    /// Calls in progress, outermost first.
+    calls: Vec<Call>,
+    /// Scopes listed by `scopes` since the program stopped, looked up by
+    /// `variablesReference - 1`.
+    references: Vec<Scope>,
+    /// Set once `configurationDone` has launched the program.
+    started: bool,
+}
+
+/// Runs a DAP server on `input`/`output` until the client disconnects.
+pub fn serve(input: impl BufRead + 'static, output: impl Write + 'static) -> anyhow::Result<()> {
+    let session = Rc::new(RefCell::new(Session {
+        input: Box::new(input),
+        output: Box::new(output),
+        seq: 1,
+        program: None,
+        source: SourceFile::new("", ""),
+        breakpoints: BTreeSet::new(),
+        mode: Mode::Continue,
+        location: Location::default(),
+        calls: Vec::new(),
+        references: Vec::new(),
+        started: false,
+    }));
+    loop {
+        let mut borrowed = session.borrow_mut();
+        let Some(request) = borrowed.read()? else {
+            return Ok(());
+        };
+        match borrowed.handle(&request, None)? {
+            Flow::Wait | Flow::Resume => {},
+            Flow::Launch => {
+                drop(borrowed);
+                run(&session)?;
+            },
+            Flow::Disconnect => return Ok(()),
+        }
+    }
+}
+
+/// Runs the launched program, sending its output and exit as events.
+fn run(session: &Rc<RefCell<Session>>) -> anyhow::Result<()> {
+    let mut sources = SourceMap::new();
+    let source = session.borrow().source.clone();
+    let file = sources.add(source.name(), source.source());
+    let result = compile_file(file, source.source()).and_then(|statements| {
+        let mut interpreter = Interpreter::with_output(Box::new(Output(session.clone())));
+        interpreter.set_hook(Box::new(Hook(session.clone())));
+        interpreter.interpret(statements)
+    });
+    let mut session = session.borrow_mut();
+    let exit_code = match result {
+        Ok(()) => 0,
+        Err(LoxError::Other(e)) if e.is::<Quit>() => 0,
+        Err(e) => {
+            session.event("output", json!({ "category": "stderr", "output": sources.render(&e) }))?;
+            match e {
+                LoxError::ScanError(_) | LoxError::ParseError(_) => 65,
+                _ => 70,
+            }
+        },
+    };
+    session.event("exited", json!({ "exitCode": exit_code }))?;
+    session.event("terminated", json!({}))?;
+    Ok(())
+}
+
+impl Session {
+    fn read(&mut self) -> anyhow::Result<Option<Value>> {
+        read_message(&mut self.input)
+    }
+    fn send(&mut self, mut message: Value) -> anyhow::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        write_message(&mut self.output, &message)
+    }
+    fn respond(&mut self, request: &Value, success: bool, body: Value) -> anyhow::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": success,
+            "body": body,
+        });
+        if !success {
+            response["message"] = body["error"].clone();
+        }
+        self.send(response)
+    }
+    fn event(&mut self, event: &str, body: Value) -> anyhow::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+    /// The stack while paused at `position` in `environment`, outermost
+    /// frame first, so that a frame's index is its id.
+    fn frames(&self, position: &Position, environment: &Scope) -> Vec<(String, Position, Scope)> {
+        let names = std::iter::once("<script>".to_string())
+            .chain(self.calls.iter().map(|call| call.name.clone()));
+        let places = self.calls.iter()
+            .map(|call| (call.position.clone(), call.environment.clone()))
+            .chain(std::iter::once((position.clone(), environment.clone())));
+        names.zip(places).map(|(name, (position, scope))| (name, position, scope)).collect()
+    }
+    /// The scope of the frame named by `frameId`, or of the innermost one.
+    fn frame_scope(&self, arguments: &Value, paused: Option<(&Position, &Scope, usize)>) -> Option<Scope> {
+        let (position, environment, _) = paused?;
+        let mut frames = self.frames(position, environment);
+        let id = arguments["frameId"].as_u64().map_or(frames.len() - 1, |id| id as usize);
+        (id < frames.len()).then(|| frames.swap_remove(id).2)
+    }
+    /// Answers `request`. `paused` holds the current position, scope and
+    /// call depth while the program is stopped.
+    fn handle(&mut self, request: &Value, paused: Option<(&Position, &Scope, usize)>) -> anyhow::Result<Flow> {
+        let arguments = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or_default();
+        let depth = paused.map_or(0, |(_, _, depth)| depth);
+        let flow = match command {
+            "initialize" => {
+                self.respond(request, true, json!({ "supportsConfigurationDoneRequest": true }))?;
+                self.event("initialized", json!({}))?;
+                Flow::Wait
+            },
+            "launch" => {
+                let Some(program) = arguments["program"].as_str() else {
+                    self.respond(request, false, json!({ "error": "Missing 'program' to launch." }))?;
+                    return Ok(Flow::Wait);
+                };
+                match std::fs::read_to_string(program) {
+                    Ok(source) => {
+                        self.program = Some(PathBuf::from(program));
+                        self.source = SourceFile::new(program, source);
+                        if arguments["stopOnEntry"].as_bool().unwrap_or(false) {
+                            self.mode = Mode::StepInto;
+                        }
+                        self.respond(request, true, json!({}))?;
+                    },
+                    Err(e) => {
+                        let error = format!("Failed to read file {}: {}", program, e);
+                        self.respond(request, false, json!({ "error": error }))?;
+                    },
+                }
+                Flow::Wait
+            },
+            "setBreakpoints" => {
+                let lines: Vec<usize> = arguments["breakpoints"].as_array()
+                    .map(|breakpoints| breakpoints.iter()
+                        .filter_map(|b| b["line"].as_u64())
+                        .map(|line| line as usize)
+                        .collect())
+                    .unwrap_or_default();
+                self.breakpoints = lines.iter().copied().collect();
+                let verified: Vec<Value> = lines.iter()
+                    .map(|line| json!({ "verified": true, "line": line }))
+                    .collect();
+                self.respond(request, true, json!({ "breakpoints": verified }))?;
+                Flow::Wait
+            },
+            "configurationDone" => {
+                self.respond(request, true, json!({}))?;
+                if self.program.is_some() && !self.started {
+                    self.started = true;
+                    Flow::Launch
+                } else {
+                    Flow::Wait
+                }
+            },
+            "threads" => {
+                self.respond(request, true, json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }))?;
+                Flow::Wait
+            },
+            "stackTrace" => {
+                let mut frames: Vec<Value> = Vec::new();
+                if let Some((position, environment, _)) = paused {
+                    for (id, (name, position, _)) in self.frames(position, environment).into_iter().enumerate().rev() {
+                        let (line, column) = self.source.line_col_utf16(position.start);
+                        frames.push(json!({
+                            "id": id,
+                            "name": name,
+                            "line": line,
+                            "column": column,
+                            "source": { "path": self.program },
+                        }));
+                    }
+                }
+                let total = frames.len();
+                self.respond(request, true, json!({ "stackFrames": frames, "totalFrames": total }))?;
+                Flow::Wait
+            },
+            "scopes" => {
+                let mut scopes = Vec::new();
+                let mut scope = self.frame_scope(arguments, paused);
+                while let Some(current) = scope {
+                    self.references.push(current.clone());
+                    scopes.push(json!({
+                        "name": scope_name(scopes.len(), &current),
+                        "variablesReference": self.references.len(),
+                        "expensive": false,
+                    }));
+                    scope = current.borrow().parent.clone();
+                }
+                self.respond(request, true, json!({ "scopes": scopes }))?;
+                Flow::Wait
+            },
+            "variables" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+                let mut variables: Vec<Value> = Vec::new();
+                if let Some(scope) = reference.checked_sub(1).and_then(|index| self.references.get(index)) {
+                    let environment = scope.borrow();
+                    let mut names: Vec<&String> = environment.variables.keys().collect();
+                    names.sort();
+                    for name in names {
+                        variables.push(json!({
+                            "name": name,
+                            "value": environment.variables[name].to_string(),
+                            "variablesReference": 0,
+                        }));
+                    }
+                }
+                self.respond(request, true, json!({ "variables": variables }))?;
+                Flow::Wait
+            },
+            "evaluate" => {
+                let expression = arguments["expression"].as_str().unwrap_or_default().trim();
+                match self.frame_scope(arguments, paused).and_then(|environment| lookup(&environment, expression)) {
+                    Some((value, _)) => self.respond(
+                        request,
+                        true,
+                        json!({ "result": value.to_string(), "variablesReference": 0 })
+                    )?,
+                    None => self.respond(
+                        request,
+                        false,
+                        json!({ "error": format!("Undefined variable '{}'.", expression) })
+                    )?,
+                }
+                Flow::Wait
+            },
+            "continue" | "next" | "stepIn" | "stepOut" if paused.is_some() => {
+                self.mode = match command {
+                    "continue" => Mode::Continue,
+                    "next" => Mode::StepOver(depth),
+                    "stepIn" => Mode::StepInto,
+                    _ => Mode::StepOut(depth),
+                };
+                let body = if command == "continue" { json!({ "allThreadsContinued": true }) } else { json!({}) };
+                self.respond(request, true, body)?;
+                Flow::Resume
+            },
+            "disconnect" | "terminate" => {
+                self.respond(request, true, json!({}))?;
+                Flow::Disconnect
+            },
+            _ => {
+                let error = format!("Unsupported request '{}'.", command);
+                self.respond(request, false, json!({ "error": error }))?;
+                Flow::Wait
+            },
+        };
+        Ok(flow)
+    }
+}
+
+/// Interpreter hook that pauses the program and serves requests while stopped.
+struct Hook(Rc<RefCell<Session>>);
+
+impl DebugHook for Hook {
+    fn before_statement(
+        &mut self,
+        statement: &Statement,
+        position: &Position,
+        environment: &Scope,
+        depth: usize
+    ) -> anyhow::Result<()> {
+        if matches!(statement, Statement::Block(_)) {
+            return Ok(());
+        }
+        let mut session = self.0.borrow_mut();
+        let line = session.source.line(position.start);
+        if !session.location.moved(line, depth, position.start) {
+            return Ok(());
+        }
+        let reason = if session.breakpoints.contains(&line) {
+            "breakpoint"
+        } else if session.mode.stops(depth) {
+            "step"
+        } else {
+            return Ok(());
+        };
+        session.references.clear();
+        session.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID }))?;
+        loop {
+            let Some(request) = session.read()? else {
+                return Err(Quit.into());
+            };
+            match session.handle(&request, Some((position, environment, depth)))? {
+                Flow::Wait | Flow::Launch => {},
+                Flow::Resume => return Ok(()),
+                Flow::Disconnect => return Err(Quit.into()),
+            }
+        }
+    }
+    fn enter_call(&mut self, function: &Function, position: &Position, environment: &Scope) {
+        self.0.borrow_mut().calls.push(Call {
+            name: function.name.clone(),
+            position: position.clone(),
+            environment: environment.clone(),
+        });
+    }
+    fn exit_call(&mut self) {
+        self.0.borrow_mut().calls.pop();
+    }
+}
+
+/// Forwards `print` output to the client as `output` events.
+struct Output(Rc<RefCell<Session>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let output = String::from_utf8_lossy(buf).into_owned();
+        self.0.borrow_mut()
+            .event("output", json!({ "category": "stdout", "output": output }))
+            .map_err(std::io::Error::other)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::{json, Value};
+
+    use super::serve;
+    use crate::{
+        lox::Capture,
+        protocol::{read_message, write_message},
+    };
+
+    /// Writes `program` to a temporary file, launches it with breakpoints on
+    /// `lines` and plays `requests` once it stops. Returns every message the
+    /// adapter sent.
+    fn session(name: &str, program: &str, lines: &[usize], requests: &[(&str, Value)]) -> Vec<Value> {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, program).unwrap();
+        let breakpoints: Vec<Value> = lines.iter().map(|line| json!({ "line": line })).collect();
+        let mut script = vec![
+            ("initialize", json!({ "adapterID": "lox" })),
+            ("launch", json!({ "program": path })),
+            ("setBreakpoints", json!({ "source": { "path": path }, "breakpoints": breakpoints })),
+            ("configurationDone", json!({})),
+        ];
+        script.extend(requests.iter().cloned());
+        let mut input = Vec::new();
+        for (seq, (command, arguments)) in script.into_iter().enumerate() {
+            let request = json!({ "seq": seq + 1, "type": "request", "command": command, "arguments": arguments });
+            write_message(&mut input, &request).unwrap();
+        }
+        let output = Capture::default();
+        serve(Cursor::new(input), output.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut output = Cursor::new(output.take().into_bytes());
+        std::iter::from_fn(|| read_message(&mut output).unwrap()).collect()
+    }
+
+    fn response<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+        messages.iter()
+            .find(|m| m["type"] == "response" && m["command"] == command)
+            .unwrap_or_else(|| panic!("no response to {}", command))
+    }
+
+    fn events<'a>(messages: &'a [Value], event: &'a str) -> impl Iterator<Item = &'a Value> {
+        messages.iter().filter(move |m| m["event"] == event)
+    }
+
+    #[test]
+    fn stack_trace_has_a_frame_per_call() {
+        let program = "\
+fun inner(n) {
+  print n;
+}
+fun outer(m) {
+  var doubled = m * 2;
+  inner(doubled);
+}
+outer(4);
+";
+        let messages = session("lox_dap_stack.lox", program, &[2], &[
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("scopes", json!({ "frameId": 1 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("evaluate", json!({ "expression": "n", "frameId": 2 })),
+            ("evaluate", json!({ "expression": "n", "frameId": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+        ]);
+        let frames = &response(&messages, "stackTrace")["body"]["stackFrames"];
+        let frames: Vec<(&str, u64, u64)> = frames.as_array().unwrap().iter()
+            .map(|f| (f["name"].as_str().unwrap(), f["id"].as_u64().unwrap(), f["line"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(frames, [("inner", 2, 2), ("outer", 1, 6), ("<script>", 0, 8)]);
+        let scopes = &response(&messages, "scopes")["body"]["scopes"];
+        assert_eq!(scopes[0]["name"], "local");
+        let variables = &response(&messages, "variables")["body"]["variables"];
+        let names: Vec<&str> = variables.as_array().unwrap().iter().map(|v| v["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["doubled", "m"]);
+        let evaluated: Vec<&Value> = messages.iter().filter(|m| m["command"] == "evaluate").collect();
+        assert_eq!(evaluated[0]["body"]["result"], "8");
+        assert_eq!(evaluated[1]["success"], false);
+        let output: String = events(&messages, "output").map(|e| e["body"]["output"].as_str().unwrap()).collect();
+        assert_eq!(output, "8\n");
+        assert_eq!(events(&messages, "exited").next().unwrap()["body"]["exitCode"], 0);
+    }
+
+    #[test]
+    fn continue_leaves_the_line() {
+        let program = "var a = 1;\nif (a > 0) print \"pos\";\nprint \"done\";\n";
+        let messages = session("lox_dap_continue.lox", program, &[2], &[
+            ("continue", json!({ "threadId": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+        ]);
+        assert_eq!(events(&messages, "stopped").count(), 1);
+        assert_eq!(events(&messages, "exited").next().unwrap()["body"]["exitCode"], 0);
+    }
+}
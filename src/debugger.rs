@@ -0,0 +1,230 @@
+use std::{collections::BTreeSet, fmt::Display, io::Write};
+
+use crate::{
+    environment::Scope,
+    interpreter::DebugHook,
+    parser::{Litteral, Statement},
+    position::Position,
+    source_map::SourceFile,
+};
+
+const HELP: &str = "\
+c, continue      run until the next breakpoint
+s, step          step into the next statement
+n, next          step over calls to the next statement
+f, finish        run until the current function returns
+b, break <line>  set a breakpoint
+d, delete <line> remove a breakpoint
+p, print <name>  show a variable, searching outwards from the current scope
+v, vars          show every variable in the current scope chain
+l, list          show the source around the current line
+q, quit          stop the program
+An empty line repeats the last command.";
+
+/// When execution should pause next.
+pub enum Mode {
+    Continue,
+    StepInto,
+    /// Stop at the next statement at or above this call depth.
+    StepOver(usize),
+    /// Stop at the next statement below this call depth.
+    StepOut(usize),
+}
+
+impl Mode {
+    /// Whether a statement at call `depth` is a stepping stop.
+    pub fn stops(&self, depth: usize) -> bool {
+        match self {
+            Mode::Continue => false,
+            Mode::StepInto => true,
+            Mode::StepOver(target) => depth <= *target,
+            Mode::StepOut(target) => depth < *target,
+        }
+    }
+}
+
+/// Where the last statement ran. A line holding several statements, like
+/// `if (a) print a;`, is a single stop: execution only pauses there again
+/// after moving to another line or call, or when a loop comes back round.
+#[derive(Default)]
+pub struct Location {
+    line: usize,
+    depth: usize,
+    start: Option<usize>,
+}
+
+impl Location {
+    /// Records a statement starting at byte `start` on `line` and returns
+    /// whether it begins a new visit to that line.
+    pub fn moved(&mut self, line: usize, depth: usize, start: usize) -> bool {
+        let revisit = self.line == line && self.depth == depth && self.start.is_some_and(|last| last < start);
+        *self = Self { line, depth, start: Some(start) };
+        !revisit
+    }
+}
+
+/// Returned from the hook when the user quits, to stop the interpreter.
+#[derive(Debug)]
+pub struct Quit;
+
+impl Display for Quit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Execution stopped by the debugger.")
+    }
+}
+
+impl std::error::Error for Quit {}
+
+/// Command-line debugger driven from stdin. Pauses before statements on
+/// breakpoint lines or while stepping, and inspects variables through the
+/// `Environment` parent chain.
+pub struct Debugger {
+    file: SourceFile,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    location: Location,
+    last_command: String,
+}
+
+impl Debugger {
+    /// Without breakpoints the debugger pauses before the first statement.
+    pub fn new(source: &str, breakpoints: &[usize]) -> Self {
+        Self {
+            file: SourceFile::new("", source),
+            breakpoints: breakpoints.iter().copied().collect(),
+            mode: if breakpoints.is_empty() { Mode::StepInto } else { Mode::Continue },
+            location: Location::default(),
+            last_command: "s".to_string(),
+        }
+    }
+    fn list(&self, line: usize) {
+        let first = line.saturating_sub(3).max(1);
+        let last = (line + 2).min(self.file.source().lines().count());
+        for number in first..=last {
+            let marker = if number == line { "->" } else if self.breakpoints.contains(&number) { " *" } else { "  " };
+            println!("{} {:4} | {}", marker, number, self.file.line_text(number));
+        }
+    }
+    /// Reads commands until one resumes execution.
+    fn prompt(&mut self, line: usize, environment: &Scope, depth: usize) -> anyhow::Result<()> {
+        loop {
+            print!("(debug) ");
+            std::io::stdout().flush()?;
+            let mut input = String::new();
+            if std::io::stdin().read_line(&mut input)? == 0 {
+                // Nobody is left to answer; let the program finish.
+                self.breakpoints.clear();
+                self.mode = Mode::Continue;
+                return Ok(());
+            }
+            let input = match input.trim() {
+                "" => self.last_command.clone(),
+                input => input.to_string(),
+            };
+            self.last_command = input.clone();
+            let (command, argument) = input.split_once(' ').unwrap_or((&input, ""));
+            let argument = argument.trim();
+            match command {
+                "c" | "continue" => {
+                    self.mode = Mode::Continue;
+                    return Ok(());
+                },
+                "s" | "step" => {
+                    self.mode = Mode::StepInto;
+                    return Ok(());
+                },
+                "n" | "next" => {
+                    self.mode = Mode::StepOver(depth);
+                    return Ok(());
+                },
+                "f" | "finish" => {
+                    self.mode = Mode::StepOut(depth);
+                    return Ok(());
+                },
+                "b" | "break" | "d" | "delete" => match argument.parse::<usize>() {
+                    Ok(number) if command.starts_with('b') => {
+                        self.breakpoints.insert(number);
+                        println!("Breakpoint at line {}", number);
+                    },
+                    Ok(number) => {
+                        self.breakpoints.remove(&number);
+                        println!("Removed breakpoint at line {}", number);
+                    },
+                    Err(_) => println!("Expected a line number."),
+                },
+                "p" | "print" => match lookup(environment, argument) {
+                    Some((value, level)) => println!("{} = {} ({})", argument, value, level),
+                    None => println!("Undefined variable '{}'.", argument),
+                },
+                "v" | "vars" => print_scopes(environment),
+                "l" | "list" => self.list(line),
+                "q" | "quit" => return Err(Quit.into()),
+                "h" | "help" => println!("{}", HELP),
+                _ => println!("Unknown command '{}', try 'help'.", command),
+            }
+        }
+    }
+}
+
+impl DebugHook for Debugger {
+    fn before_statement(
+        &mut self,
+        statement: &Statement,
+        position: &Position,
+        environment: &Scope,
+        depth: usize
+    ) -> anyhow::Result<()> {
+        // Blocks are stepped through one statement at a time instead.
+        if matches!(statement, Statement::Block(_)) {
+            return Ok(());
+        }
+        let line = self.file.line(position.start);
+        if !self.location.moved(line, depth, position.start) {
+            return Ok(());
+        }
+        if !self.mode.stops(depth) && !self.breakpoints.contains(&line) {
+            return Ok(());
+        }
+        self.list(line);
+        self.prompt(line, environment, depth)
+    }
+}
+
+/// Label for the scope `level` steps out from the current one.
+pub fn scope_name(level: usize, scope: &Scope) -> String {
+    match scope.borrow().parent {
+        None => "global".to_string(),
+        Some(_) if level == 0 => "local".to_string(),
+        Some(_) => format!("enclosing +{}", level),
+    }
+}
+
+/// Finds `name` in `environment` or one of its parents.
+pub fn lookup(environment: &Scope, name: &str) -> Option<(Litteral, String)> {
+    let mut scope = environment.clone();
+    let mut level = 0;
+    loop {
+        if let Some(value) = scope.borrow().variables.get(name) {
+            return Some((value.clone(), scope_name(level, &scope)));
+        }
+        let parent = scope.borrow().parent.clone()?;
+        scope = parent;
+        level += 1;
+    }
+}
+
+fn print_scopes(environment: &Scope) {
+    let mut scope = Some(environment.clone());
+    let mut level = 0;
+    while let Some(current) = scope {
+        println!("{}:", scope_name(level, &current));
+        let environment = current.borrow();
+        let mut names: Vec<&String> = environment.variables.keys().collect();
+        names.sort();
+        for name in names {
+            println!("    {} = {}", name, environment.variables[name]);
+        }
+        scope = environment.parent.clone();
+        level += 1;
+    }
+}
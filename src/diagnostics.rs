@@ -0,0 +1,124 @@
+use std::fmt::{Display, Write};
+
+use crate::{position::Position, source_map::SourceFile};
+
+// Error codes, grouped by the phase that reports them:
+// E00xx scanner, E01xx parser, E02xx resolver, E03xx runtime, W00xx linter.
+pub const INVALID_TOKEN: &str = "E0001";
+pub const EXPECTED_TOKEN: &str = "E0100";
+pub const EXPECTED_EXPRESSION: &str = "E0101";
+pub const INVALID_ASSIGNMENT: &str = "E0102";
+pub const OWN_INITIALIZER: &str = "E0200";
+pub const REDECLARED_LOCAL: &str = "E0201";
+pub const INVALID_RETURN: &str = "E0202";
+pub const INVALID_THIS: &str = "E0203";
+pub const INVALID_SUPER: &str = "E0204";
+pub const OPERAND_TYPE: &str = "E0300";
+pub const UNDEFINED_VARIABLE: &str = "E0301";
+pub const NOT_CALLABLE: &str = "E0302";
+pub const ARITY_MISMATCH: &str = "E0303";
+pub const NOT_AN_INSTANCE: &str = "E0304";
+pub const UNDEFINED_PROPERTY: &str = "E0305";
+pub const INVALID_SUPERCLASS: &str = "E0306";
+pub const NATIVE_ERROR: &str = "E0307";
+pub const STACK_OVERFLOW: &str = "E0308";
+pub const UNUSED_VARIABLE: &str = "W0001";
+pub const SHADOWED_DECLARATION: &str = "W0002";
+pub const UNREACHABLE_CODE: &str = "W0003";
+pub const ASSIGNMENT_IN_CONDITION: &str = "W0004";
+pub const NIL_COMPARISON: &str = "W0005";
+pub const CONSTANT_CONDITION: &str = "W0006";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(unused)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A message about a span of source code. Rendered against the source by
+/// `render_file`, or as a single line without location through `Display`.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Position,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Position) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Position) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message, span)
+        }
+    }
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+    /// Formats the diagnostic with the offending source line and a caret
+    /// underline below the span, naming `file` in the location line when it
+    /// has a name.
+    pub fn render_file(&self, file: &SourceFile) -> String {
+        let (line, col) = file.line_col(self.span.start);
+        let (end_line, end_col) = file.line_col(self.span.end);
+        let text = file.line_text(line);
+        let gutter = " ".repeat(line.to_string().len());
+        // Carets line up with characters, not bytes.
+        let chars = |from: usize, to: usize| text.get(from - 1..to - 1).map_or(0, |s| s.chars().count());
+        let start = chars(1, col);
+        let width = if end_line == line && end_col > col { chars(col, end_col).max(1) } else { 1 };
+        let name = match file.name() {
+            "" => String::new(),
+            name => format!("{}, ", name),
+        };
+
+        let mut out = String::new();
+        writeln!(out, "{}[{}]: {}", self.severity, self.code, self.message).unwrap();
+        writeln!(out, "{}--> {}line {}, column {}", gutter, name, line, col).unwrap();
+        writeln!(out, "{} |", gutter).unwrap();
+        writeln!(out, "{} | {}", line, text).unwrap();
+        writeln!(out, "{} | {}{}", gutter, " ".repeat(start), "^".repeat(width)).unwrap();
+        for note in &self.notes {
+            writeln!(out, "{} = note: {}", gutter, note).unwrap();
+        }
+        if let Some(help) = &self.help {
+            writeln!(out, "{} = help: {}", gutter, help).unwrap();
+        }
+        out
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use crate::{
+    chunk::{Chunk, OpCode},
+    source_map::SourceFile,
+};
+
+/// Renders `chunk` and every function chunk nested in it, one instruction
+/// per line: offset, line in `file` (`|` when unchanged), opcode and operands.
+pub fn disassemble(chunk: &Chunk, name: &str, file: &SourceFile) -> String {
+    let mut out = String::new();
+    disassemble_into(&mut out, chunk, name, file);
+    out
+}
+
+fn disassemble_into(out: &mut String, chunk: &Chunk, name: &str, file: &SourceFile) {
+    writeln!(out, "== {} ==", name).unwrap();
+    for offset in 0..chunk.code.len() {
+        writeln!(out, "{}", instruction(chunk, offset, file)).unwrap();
+    }
+    if !chunk.constants.is_empty() {
+        writeln!(out, "-- constants --").unwrap();
+        for (index, constant) in chunk.constants.iter().enumerate() {
+            writeln!(out, "{:4} {:?}", index, constant).unwrap();
+        }
+    }
+    for class in &chunk.classes {
+        for &method in &class.methods {
+            let function = &chunk.functions[method];
+            writeln!(out).unwrap();
+            let name = format!("{}.{}", class.name, function.declaration.name);
+            disassemble_into(out, &function.chunk, &name, file);
+        }
+    }
+    let methods: Vec<usize> = chunk.classes.iter().flat_map(|c| c.methods.clone()).collect();
+    for (index, function) in chunk.functions.iter().enumerate() {
+        if methods.contains(&index) {
+            continue;
+        }
+        writeln!(out).unwrap();
+        disassemble_into(out, &function.chunk, &function.declaration.name, file);
+    }
+}
+
+/// Formats the single instruction at `offset`.
+pub fn instruction(chunk: &Chunk, offset: usize, file: &SourceFile) -> String {
+    let line_of = |offset: usize| file.line(chunk.positions[offset].start);
+    let line = if offset > 0 && line_of(offset) == line_of(offset - 1) {
+        "   |".to_string()
+    } else {
+        format!("{:4}", line_of(offset))
+    };
+    let constant = |index: usize| format!("{:4} '{:?}'", index, chunk.constants[index]);
+    let scope = |depth: Option<usize>| match depth {
+        Some(depth) => format!("local +{}", depth),
+        None => "global".to_string(),
+    };
+    let text = match &chunk.code[offset] {
+        OpCode::Constant(index) => format!("{:<16} {}", "CONSTANT", constant(*index)),
+        OpCode::Nil => "NIL".to_string(),
+        OpCode::Pop => "POP".to_string(),
+        OpCode::Print => "PRINT".to_string(),
+        OpCode::Define(name) => format!("{:<16} {}", "DEFINE", constant(*name)),
+        OpCode::Get(name, depth) => format!("{:<16} {} ({})", "GET", constant(*name), scope(*depth)),
+        OpCode::Set(name, depth) => format!("{:<16} {} ({})", "SET", constant(*name), scope(*depth)),
+        OpCode::Binary(op) => format!("{:<16} {:>4}", "BINARY", op.to_string()),
+        OpCode::Unary(op) => format!("{:<16} {:>4}", "UNARY", op.to_string()),
+        OpCode::Jump(target) => format!("{:<16} {:4} -> {:04}", "JUMP", offset, target),
+        OpCode::JumpIfFalse(target) => format!("{:<16} {:4} -> {:04}", "JUMP_IF_FALSE", offset, target),
+        OpCode::JumpIfTrue(target) => format!("{:<16} {:4} -> {:04}", "JUMP_IF_TRUE", offset, target),
+        OpCode::PushScope => "PUSH_SCOPE".to_string(),
+        OpCode::PopScope => "POP_SCOPE".to_string(),
+        OpCode::Closure(index) => format!(
+            "{:<16} {:4} <fn {}>",
+            "CLOSURE",
+            index,
+            chunk.functions[*index].declaration.name
+        ),
+        OpCode::Call(argc) => format!("{:<16} {:4}", "CALL", argc),
+        OpCode::Return => "RETURN".to_string(),
+        OpCode::Class(index) => {
+            let class = &chunk.classes[*index];
+            match &class.superclass {
+                Some((parent, depth, _)) => format!(
+                    "{:<16} {:4} '{}' < '{}' ({})",
+                    "CLASS",
+                    index,
+                    class.name,
+                    parent,
+                    scope(*depth)
+                ),
+                None => format!("{:<16} {:4} '{}'", "CLASS", index, class.name),
+            }
+        },
+        OpCode::GetProperty(name) => format!("{:<16} {}", "GET_PROPERTY", constant(*name)),
+        OpCode::SetProperty(name) => format!("{:<16} {}", "SET_PROPERTY", constant(*name)),
+        OpCode::This => "THIS".to_string(),
+        OpCode::GetSuper(name) => format!("{:<16} {}", "GET_SUPER", constant(*name)),
+        OpCode::Interpolate(count) => format!("{:<16} {:4}", "INTERPOLATE", count),
+    };
+    format!("{:04} {} {}", offset, line, text)
+}
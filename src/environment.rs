@@ -1,13 +1,16 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
 use anyhow::anyhow;
 
 use crate::parser::Litteral;
 
 
-#[derive(Clone)]
+/// A scope shared between the interpreter and every closure that captured it.
+pub type Scope = Rc<RefCell<Environment>>;
+
+#[derive(Default)]
 pub struct Environment {
     pub variables: HashMap<String, Litteral>, 
-    pub parent: Option<Box<Environment>>
+    pub parent: Option<Scope>
 }
 
 impl Environment {
@@ -17,18 +20,21 @@ impl Environment {
             parent: None
         }
     }
-    pub fn with_parent(parent: Environment) -> Self {
+    pub fn with_parent(parent: Scope) -> Self {
         Self {
             variables: HashMap::new(),
-            parent: Some(Box::new(parent))
+            parent: Some(parent)
         }
     }
+    pub fn scope(self) -> Scope {
+        Rc::new(RefCell::new(self))
+    }
     pub fn get(&self, ident: &String) -> anyhow::Result<Litteral> {
         if let Some(var) = self.variables.get(ident) {
             Ok(var.clone())
         } else {
-            if let Some(parent) = self.parent.clone() {
-                return parent.get(ident);
+            if let Some(parent) = &self.parent {
+                return parent.borrow().get(ident);
             }
             Err(anyhow!("Undefined variable '{}'.", ident))
         }
@@ -38,24 +44,40 @@ impl Environment {
             self.define(ident, value);
             Ok(())
         } else {
-            match &mut self.parent {
-                Some(parent) => parent.assign(ident, value),
+            match &self.parent {
+                Some(parent) => parent.borrow_mut().assign(ident, value),
                 None => Err(anyhow!("attempt to assign to undefined variable: {}", ident))
             }
         }
     }
+    /// Reads `ident` from the scope exactly `depth` parents up, as computed
+    /// by the resolver.
+    pub fn get_at(&self, depth: usize, ident: &String) -> anyhow::Result<Litteral> {
+        if depth == 0 {
+            return self.variables.get(ident).cloned()
+                .ok_or_else(|| anyhow!("Undefined variable '{}'.", ident));
+        }
+        match &self.parent {
+            Some(parent) => parent.borrow().get_at(depth - 1, ident),
+            None => Err(anyhow!("Undefined variable '{}'.", ident))
+        }
+    }
+    pub fn assign_at(&mut self, depth: usize, ident: String, value: Litteral) -> anyhow::Result<()> {
+        if depth == 0 {
+            self.define(ident, value);
+            return Ok(());
+        }
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().assign_at(depth - 1, ident, value),
+            None => Err(anyhow!("attempt to assign to undefined variable: {}", ident))
+        }
+    }
     pub fn define(&mut self, ident: String, value: Litteral) {
         self.variables.insert(ident, value);
     }
 }
 
 
-impl Default for Litteral {
-    fn default() -> Self {
-        Litteral::Nil
-    }
-}
-
 impl Display for Environment {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Variables:")?; 
@@ -65,6 +87,3 @@ impl Display for Environment {
         Ok(())
     }
 }
-
-
-
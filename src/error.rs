@@ -0,0 +1,46 @@
+use thiserror::Error;
+
+use crate::diagnostics::Diagnostic;
+
+/// Errors returned to callers of the interpreter, one variant per phase.
+/// Only `main` turns these into process exit codes.
+#[derive(Debug, Error)]
+pub enum LoxError {
+    /// Invalid tokens found by the scanner.
+    #[error("{}", join(.0))]
+    ScanError(Vec<Diagnostic>),
+    /// Syntax errors and static errors found by the resolver.
+    #[error("{}", join(.0))]
+    ParseError(Vec<Diagnostic>),
+    #[error("{0}")]
+    RuntimeError(Box<Diagnostic>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl LoxError {
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            LoxError::ScanError(diagnostics) | LoxError::ParseError(diagnostics) => diagnostics,
+            LoxError::RuntimeError(diagnostic) => std::slice::from_ref(diagnostic.as_ref()),
+            LoxError::Io(_) | LoxError::Other(_) => &[],
+        }
+    }
+}
+
+impl From<anyhow::Error> for LoxError {
+    /// Runtime errors travel through `anyhow` inside the interpreter; this
+    /// recovers the `Diagnostic` they carry.
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<Diagnostic>() {
+            Ok(diagnostic) => LoxError::RuntimeError(Box::new(diagnostic)),
+            Err(error) => LoxError::Other(error),
+        }
+    }
+}
+
+fn join(diagnostics: &[Diagnostic]) -> String {
+    diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n")
+}
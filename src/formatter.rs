@@ -0,0 +1,448 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    diagnostics::Diagnostic,
+    error::LoxError,
+    parser::{AstFactory, BinaryOperator, Function, Node, Statement},
+    position::Position,
+    scanner::{self, Token, TokenType},
+    source_map::SourceFile,
+};
+
+const INDENT: &str = "    ";
+
+/// A comment waiting to be printed before the next thing that follows it.
+struct Comment {
+    text: String,
+    position: Position,
+    /// Whether the comment ends a line that has code before it.
+    trailing: bool,
+    /// Whether the comment sits between two tokens on the same line.
+    inline: bool,
+    /// Start of the last code token before the comment.
+    after: usize,
+}
+
+impl Comment {
+    /// Whether the comment belongs on the same line as some code.
+    fn after_code(&self) -> bool {
+        self.trailing || self.inline
+    }
+}
+
+/// Pretty-prints a parsed program with canonical layout, putting comments
+/// back next to the code they were written around.
+struct Formatter {
+    file: SourceFile,
+    out: String,
+    indent: usize,
+    comments: VecDeque<Comment>,
+    /// Source text of literal tokens by start offset, so numbers and
+    /// strings are printed as written.
+    literals: HashMap<usize, String>,
+    /// Positions of each `{` and its matching `}`, in source order.
+    braces: Vec<(Position, Position)>,
+    next_brace: usize,
+    /// Last source line printed, used to keep blank lines between statements.
+    last_line: usize,
+    /// Set when the next line continues the previous one.
+    joined: bool,
+    /// Set when the last line ends in a comment and can't be continued.
+    commented: bool,
+    /// Code tokens as `(start, end, closes)`, where `closes` marks the `;`
+    /// and `)` that end whatever line they follow.
+    code: Vec<(usize, usize, bool)>,
+    /// Every code token starting before this offset has been printed.
+    written: usize,
+}
+
+/// Formats `source`, failing with the same errors as `compile` when it does
+/// not scan or parse.
+pub fn format(source: &str) -> Result<String, LoxError> {
+    let tokens: Vec<Token> = scanner::scan_with_trivia(source.to_string());
+    let invalid: Vec<Diagnostic> = tokens.iter().filter_map(Token::diagnostic).collect();
+    if !invalid.is_empty() {
+        return Err(LoxError::ScanError(invalid));
+    }
+    let mut formatter = Formatter::new(SourceFile::new("", source), &tokens);
+    let code: Vec<Token> = tokens.into_iter().filter(|token| !token.is_trivia()).collect();
+    let (statements, errors) = AstFactory::new(code).parse_statements();
+    if !errors.is_empty() {
+        return Err(LoxError::ParseError(errors));
+    }
+    formatter.statements(&statements);
+    formatter.flush_comments(None);
+    Ok(formatter.out)
+}
+
+impl Formatter {
+    fn new(file: SourceFile, tokens: &[Token]) -> Self {
+        let mut comments = VecDeque::new();
+        let mut literals = HashMap::new();
+        let mut braces = Vec::new();
+        let mut open: Vec<usize> = Vec::new();
+        let mut code = Vec::new();
+        let mut code_line = 0;
+        for (index, token) in tokens.iter().enumerate() {
+            match &token.token_type {
+                TokenType::Comment(_) => {
+                    let followed = tokens[index + 1..].iter()
+                        .find(|next| !next.is_trivia())
+                        .is_some_and(|next| file.line(next.position.start) == file.line(token.position.end));
+                    let after_code = code_line == file.line(token.position.start);
+                    comments.push_back(Comment {
+                        text: token.raw.trim_end().to_string(),
+                        position: token.position.clone(),
+                        trailing: after_code && !followed,
+                        inline: after_code && followed,
+                        after: code.last().map_or(0, |&(start, _, _)| start),
+                    });
+                },
+                TokenType::Number(_)
+                | TokenType::StringLitteral(_)
+                | TokenType::Interpolation(_)
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Nil => {
+                    literals.insert(token.position.start, token.raw.clone());
+                },
+                TokenType::LeftBrace => {
+                    open.push(braces.len());
+                    braces.push((token.position.clone(), token.position.clone()));
+                },
+                TokenType::RightBrace => {
+                    if let Some(index) = open.pop() {
+                        braces[index].1 = token.position.clone();
+                    }
+                },
+                _ => {},
+            }
+            if !token.is_trivia() {
+                let closes = matches!(token.token_type, TokenType::SemiColon | TokenType::RightParen);
+                code.push((token.position.start, token.position.end, closes));
+                code_line = file.line(token.position.end);
+            }
+        }
+        Self {
+            file,
+            out: String::new(),
+            indent: 0,
+            comments,
+            literals,
+            braces,
+            next_brace: 0,
+            last_line: 0,
+            joined: false,
+            commented: false,
+            code,
+            written: 0,
+        }
+    }
+
+    /// Prints the comments that come before `until`, or all of them.
+    fn flush_comments(&mut self, until: Option<&Position>) {
+        while let Some(comment) = self.comments.front() {
+            if until.is_some_and(|until| comment.position.start >= until.start) {
+                break;
+            }
+            let comment = self.comments.pop_front().unwrap();
+            if comment.after_code() && !self.out.is_empty() {
+                self.join();
+            } else {
+                self.blank_line(self.file.line(comment.position.start));
+                // Comments that share a line with code don't move it on.
+                self.last_line = self.last_line.max(self.file.line(comment.position.end));
+            }
+            self.line(comment.text);
+        }
+    }
+
+    /// Takes the comments written just before `start` on its line, to be
+    /// printed in front of the code there.
+    fn inline_comments(&mut self, start: &Position) -> String {
+        let mut prefix = String::new();
+        while let Some(comment) = self.comments.front() {
+            let before = comment.position.start < start.start
+                && self.file.line(comment.position.start) == self.file.line(start.start);
+            if !comment.inline || !before {
+                break;
+            }
+            prefix.push_str(&self.comments.pop_front().unwrap().text);
+            prefix.push(' ');
+        }
+        prefix
+    }
+
+    /// Keeps a single blank line where the source had one or more before `line`.
+    fn blank_line(&mut self, line: usize) {
+        let opened = self.out.is_empty() || self.out.ends_with("{\n");
+        if !opened && line > self.last_line + 1 {
+            self.out.push('\n');
+        }
+    }
+
+    /// Makes the next `line` continue the last one after a space, unless a
+    /// comment ends it.
+    fn join(&mut self) {
+        if self.commented {
+            return;
+        }
+        self.out.pop();
+        self.joined = true;
+    }
+
+    fn line(&mut self, text: String) {
+        if self.joined {
+            self.out.push(' ');
+            self.joined = false;
+        } else {
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+        }
+        self.out.push_str(&text);
+        self.commented = false;
+        let mut next = self.code.partition_point(|&(start, _, _)| start < self.written);
+        while let Some(&(_, end, true)) = self.code.get(next) {
+            self.written = end;
+            next += 1;
+        }
+        // Comments whose preceding code is now printed end this line.
+        while let Some(comment) = self.comments.front() {
+            if !comment.after_code() || comment.after >= self.written {
+                break;
+            }
+            let comment = self.comments.pop_front().unwrap();
+            self.out.push(' ');
+            self.out.push_str(&comment.text);
+            self.commented = true;
+        }
+        self.out.push('\n');
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            if let Some(start) = self.start(statement) {
+                self.flush_comments(Some(&start));
+                self.blank_line(self.file.line(start.start));
+            }
+            self.statement(String::new(), statement);
+        }
+    }
+
+    /// Where `statement` starts. Blocks start at their `{`, which the AST
+    /// does not record.
+    fn start(&self, statement: &Statement) -> Option<Position> {
+        match statement {
+            Statement::Block(_) => self.braces.get(self.next_brace).map(|(open, _)| open.clone()),
+            Statement::For(None, None, None, body) => self.start(body),
+            _ => statement.position(),
+        }
+    }
+
+    /// Writes `head` and the next brace pair around whatever `body` writes.
+    fn braces(&mut self, head: String, body: impl FnOnce(&mut Self)) {
+        let (open, close) = self.braces[self.next_brace].clone();
+        self.next_brace += 1;
+        let (length, joined) = (self.out.len(), self.joined);
+        self.written = self.written.max(open.end);
+        self.line(format!("{}{{", head));
+        let (inner, commented) = (self.out.len(), self.commented);
+        self.indent += 1;
+        body(self);
+        self.flush_comments(Some(&close));
+        self.indent -= 1;
+        self.last_line = self.last_line.max(self.file.line(close.end));
+        self.written = self.written.max(close.end);
+        if self.out.len() == inner && !commented {
+            self.out.truncate(length);
+            self.joined = joined;
+            self.line(format!("{}{{}}", head));
+        } else {
+            self.line("}".to_string());
+        }
+    }
+
+    /// Writes `statement` with `prefix` in front of its first line.
+    fn statement(&mut self, prefix: String, statement: &Statement) {
+        if let Some(position) = self.start(statement) {
+            self.last_line = self.last_line.max(self.file.line(position.end));
+            self.written = self.written.max(position.end);
+        }
+        match statement {
+            Statement::Block(statements) => self.braces(prefix, |f| f.statements(statements)),
+            Statement::If(condition, then, els) => {
+                let head = format!("{}if ({})", prefix, self.expr(condition));
+                self.body(head, then);
+                if let Some(els) = els {
+                    if matches!(then.as_ref(), Statement::Block(_)) {
+                        self.join();
+                    }
+                    match els.as_ref() {
+                        Statement::If(..) => self.statement("else ".to_string(), els),
+                        _ => self.body("else".to_string(), els),
+                    }
+                }
+            },
+            Statement::While(condition, body) => {
+                let head = format!("{}while ({})", prefix, self.expr(condition));
+                self.body(head, body);
+            },
+            Statement::For(init, condition, increment, body) => {
+                let init = match init {
+                    Some(init) => self.simple(init),
+                    None => ";".to_string(),
+                };
+                let condition = condition.as_ref().map(|c| format!(" {}", self.expr(c))).unwrap_or_default();
+                let increment = increment.as_ref().map(|i| format!(" {}", self.expr(i))).unwrap_or_default();
+                let head = format!("{}for ({}{};{})", prefix, init, condition, increment);
+                self.body(head, body);
+            },
+            Statement::Function(function) => self.function(format!("{}fun ", prefix), function),
+            Statement::Class(name, superclass, methods, _) => {
+                let superclass = superclass.as_ref().map(|s| format!(" < {}", self.expr(s))).unwrap_or_default();
+                self.braces(format!("{}class {}{} ", prefix, name, superclass), |f| {
+                    for method in methods {
+                        f.flush_comments(Some(&method.position));
+                        f.blank_line(f.file.line(method.position.start));
+                        f.function(String::new(), method);
+                    }
+                });
+            },
+            _ => {
+                let text = self.simple(statement);
+                self.line(format!("{}{}", prefix, text));
+            },
+        }
+    }
+
+    /// Text of a statement that fits on one line.
+    fn simple(&mut self, statement: &Statement) -> String {
+        match statement {
+            Statement::Expression(expr) => format!("{};", self.expr(expr)),
+            Statement::Print(expr) => format!("print {};", self.expr(expr)),
+            Statement::Return(None, _) => "return;".to_string(),
+            Statement::Return(Some(expr), _) => format!("return {};", self.expr(expr)),
+            Statement::VarDecl(name, initializer, _) if self.implicit_nil(initializer) => format!("var {};", name),
+            Statement::VarDecl(name, initializer, _) => format!("var {} = {};", name, self.expr(initializer)),
+            _ => unreachable!("only declarations and expressions can start a for loop"),
+        }
+    }
+
+    /// Writes `head` and `body`: on the same line when the body is a block,
+    /// indented on the next one otherwise.
+    fn body(&mut self, head: String, body: &Statement) {
+        match body {
+            Statement::Block(_) => self.statement(format!("{} ", head), body),
+            _ => {
+                self.line(head);
+                self.indent += 1;
+                self.statement(String::new(), body);
+                self.indent -= 1;
+            },
+        }
+    }
+
+    fn function(&mut self, head: String, function: &Function) {
+        let head = format!("{}{}({}) ", head, function.name, function.params.join(", "));
+        self.last_line = self.last_line.max(self.file.line(function.position.end));
+        self.written = self.written.max(function.position.end);
+        self.braces(head, |f| f.statements(&function.body));
+    }
+
+    /// Whether `expr` is the `nil` the parser fills in for `var x;`.
+    fn implicit_nil(&self, expr: &Node) -> bool {
+        match expr {
+            Node::Litteral(_, position) => !self.literals.contains_key(&position.start),
+            _ => false,
+        }
+    }
+
+    fn expr(&mut self, expr: &Node) -> String {
+        let position = expr.position();
+        let comments = self.inline_comments(&position);
+        self.last_line = self.last_line.max(self.file.line(position.end));
+        self.written = self.written.max(position.end);
+        let text = match expr {
+            Node::Binary { left, right, operator, .. } => {
+                let operator = match operator {
+                    BinaryOperator::And => "and".to_string(),
+                    BinaryOperator::Or => "or".to_string(),
+                    operator => operator.to_string(),
+                };
+                format!("{} {} {}", self.expr(left), operator, self.expr(right))
+            },
+            Node::Parenthesis(inner) => format!("({})", self.expr(inner)),
+            Node::Unary(operator, operand, _) => format!("{}{}", operator, self.expr(operand)),
+            Node::Litteral(value, position) => match self.literals.get(&position.start) {
+                Some(raw) => raw.clone(),
+                None => value.to_string(),
+            },
+            Node::Identifier(name, _, _) => name.clone(),
+            Node::Assignment(name, value, _, _) => format!("{} = {}", name, self.expr(value)),
+            Node::Call { callee, arguments, .. } => {
+                let callee = self.expr(callee);
+                let arguments: Vec<String> = arguments.iter().map(|argument| self.expr(argument)).collect();
+                format!("{}({})", callee, arguments.join(", "))
+            },
+            Node::Get(object, name, _) => format!("{}.{}", self.expr(object), name),
+            Node::Set(object, name, value, _) => format!("{}.{} = {}", self.expr(object), name, self.expr(value)),
+            Node::This(_) => "this".to_string(),
+            Node::Super(method, _) => format!("super.{}", method),
+            // Literal segments come back from `literals` with their quotes
+            // and `${`/`}` delimiters.
+            Node::Interpolation(parts, _) => parts.iter().map(|part| self.expr(part)).collect(),
+        };
+        comments + &text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(source: &str, expected: &str) {
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        // `fmt --check` must accept its own output.
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn trailing_comments_stay_on_their_line() {
+        formats(
+            "if (x) // why\n  print x;\nfun g() {}\n",
+            "if (x) // why\n    print x;\nfun g() {}\n",
+        );
+        formats("if (x) {\n  print 1; // one\n}\n", "if (x) {\n    print 1; // one\n}\n");
+        formats("fun f() { // nothing\n}\n", "fun f() { // nothing\n}\n");
+        formats("print 1; print 2; // two\n", "print 1;\nprint 2; // two\n");
+        formats("{ print 1; } // block\n", "{\n    print 1;\n} // block\n");
+    }
+
+    #[test]
+    fn comments_between_tokens_stay_inline() {
+        formats("print 1 + /* c */ 2;\n", "print 1 + /* c */ 2;\n");
+        formats("print f(/* a */ 1, /* b */ 2);\n", "print f(/* a */ 1, /* b */ 2);\n");
+    }
+
+    #[test]
+    fn commented_brace_is_not_joined() {
+        formats(
+            "if (x) {\n} // c\nelse {\n  print 3;\n}\n",
+            "if (x) {} // c\nelse {\n    print 3;\n}\n",
+        );
+    }
+
+    #[test]
+    fn blank_lines_follow_code() {
+        formats("// a\nprint 1;\n\n\nprint 2; // b\nprint 3;\n", "// a\nprint 1;\n\nprint 2; // b\nprint 3;\n");
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let source = "// header\n\nvar a=1;  // one\nfun add(x,y){\n// body\nreturn x+/* sum */y;\n}\n\nclass A < B {\n  init() { this.x = 1; } // ctor\n}\nwhile (a < 3) a = a + 1;\nfor (var i = 0; i < 2; i = i + 1) {\n  print i;\n}\n/* tail */\n";
+        let formatted = format(source).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+}
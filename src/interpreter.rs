@@ -1,65 +1,218 @@
 
-use crate::{environment::Environment, parser::{BinaryOperator, Litteral, Node, Statement, UnaryOperator}, position::Position};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, io::Write, rc::Rc};
+
+use crate::{chunk::Chunk, diagnostics::{self, Diagnostic}, environment::{Environment, Scope}, error::LoxError, parser::{BinaryOperator, Function, Litteral, Node, Statement, UnaryOperator}, position::Position};
 use anyhow::anyhow;
 
-#[allow(unused)]
+/// Most Lox calls that can be active at once, in either backend. Deeper
+/// recursion is reported as a runtime error instead of overflowing the
+/// host stack.
+pub const MAX_CALL_DEPTH: usize = 256;
+
 pub struct Interpreter {
-    environment: Environment,
-    global: Environment,
+    environment: Scope,
+    global: Scope,
+    /// Where `print` statements write to; stdout unless set by `with_output`.
+    output: Box<dyn Write>,
+    hook: Option<Box<dyn DebugHook>>,
+    /// Number of Lox calls currently on the stack.
+    depth: usize,
+}
+
+/// Observer notified before every statement runs, used by debuggers.
+/// Returning an error aborts execution with that error.
+pub trait DebugHook {
+    fn before_statement(
+        &mut self,
+        statement: &Statement,
+        position: &Position,
+        environment: &Scope,
+        depth: usize
+    ) -> anyhow::Result<()>;
+    /// Called when `function` is called from `position`, before its body
+    /// runs. `environment` is the caller's scope.
+    fn enter_call(&mut self, _function: &Function, _position: &Position, _environment: &Scope) {}
+    /// Called when the innermost call returns, normally or with an error.
+    fn exit_call(&mut self) {}
+}
+
+/// A function value together with the scope it was declared in. `chunk`
+/// holds the compiled body when the value was created by the VM.
+pub struct Closure {
+    pub declaration: Rc<Function>,
+    pub closure: Scope,
+    pub is_initializer: bool,
+    pub chunk: Option<Rc<Chunk>>,
+}
+
+/// Signature of a host function callable from Lox.
+pub type NativeFn = dyn Fn(&[Litteral]) -> anyhow::Result<Litteral>;
+
+/// A function implemented by the host and callable from Lox.
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub function: Box<NativeFn>,
+}
+
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Rc<Closure>>,
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: HashMap<String, Litteral>,
+}
+
+impl Closure {
+    /// Returns a copy of this method whose scope has `this` bound to `instance`.
+    pub fn bind(&self, instance: Rc<RefCell<Instance>>) -> Closure {
+        let mut scope = Environment::with_parent(self.closure.clone());
+        scope.define("this".to_string(), Litteral::Instance(instance));
+        Closure {
+            declaration: self.declaration.clone(),
+            closure: scope.scope(),
+            is_initializer: self.is_initializer,
+            chunk: self.chunk.clone(),
+        }
+    }
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+}
+
+impl Class {
+    /// Looks `name` up on this class, then up the superclass chain.
+    pub fn find_method(&self, name: &str) -> Option<Rc<Closure>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+    pub fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+}
+
+impl Instance {
+    pub fn get(instance: &Rc<RefCell<Instance>>, name: &str) -> Option<Litteral> {
+        if let Some(value) = instance.borrow().fields.get(name) {
+            return Some(value.clone());
+        }
+        let method = instance.borrow().class.find_method(name)?;
+        Some(Litteral::Function(Rc::new(method.bind(instance.clone()))))
+    }
+}
+
+/// Defines the built-in native functions in `global`.
+pub fn define_builtins(global: &Scope) {
+    define_native(global, "clock", 0, |_| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+        Ok(Litteral::Number(now.as_secs_f64()))
+    });
+}
+
+pub fn define_native(
+    scope: &Scope,
+    name: &str,
+    arity: usize,
+    function: impl Fn(&[Litteral]) -> anyhow::Result<Litteral> + 'static
+) {
+    let native = NativeFunction {
+        name: name.to_string(),
+        arity,
+        function: Box::new(function),
+    };
+    scope.borrow_mut().define(name.to_string(), Litteral::Native(Rc::new(native)));
+}
+
+pub fn runtime_error(code: &'static str, message: impl Display, position: &Position) -> anyhow::Error {
+    Diagnostic::error(code, message.to_string(), position.clone()).into()
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        Self::with_output(Box::new(std::io::stdout()))
+    }
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let global = Environment::new().scope();
+        define_builtins(&global);
         Self {
-            environment: Environment::new(),
-            global: Environment::new(),
+            environment: global.clone(),
+            global,
+            output,
+            hook: None,
+            depth: 0,
+        }
+    }
+    pub fn globals(&self) -> &Scope {
+        &self.global
+    }
+    pub fn set_hook(&mut self, hook: Box<dyn DebugHook>) {
+        self.hook = Some(hook);
+    }
+    /// The innermost scope currently in effect.
+    pub fn environment(&self) -> &Scope {
+        &self.environment
+    }
+    /// Registers `function` as a global callable from Lox with `arity` arguments.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Litteral]) -> anyhow::Result<Litteral> + 'static
+    ) {
+        define_native(&self.global, name, arity, function);
+    }
+    /// Evaluates a single expression against the current scope.
+    pub fn evaluate(&mut self, expr: &Node) -> Result<Litteral, LoxError> {
+        match self.evaluate_expr(expr)? {
+            Node::Litteral(lit, _) => Ok(lit),
+            _ => unreachable!(),
         }
     }
-    pub fn interpret(&mut self, statements: Vec<Statement>) -> anyhow::Result<()> {
+    pub fn interpret(&mut self, statements: Vec<Statement>) -> Result<(), LoxError> {
         for stmt in statements {
             self.execute(stmt)?;
         }
         Ok(())
     }
-    pub fn execute(&mut self, statement: Statement) -> anyhow::Result<()> {
+    /// Executes a single statement. Returns `Some` with the returned value
+    /// when a `return` statement is hit, so callers can unwind to the call.
+    pub fn execute(&mut self, statement: Statement) -> anyhow::Result<Option<Litteral>> {
+        if let Some(hook) = &mut self.hook {
+            if let Some(position) = statement.position() {
+                hook.before_statement(&statement, &position, &self.environment, self.depth)?;
+            }
+        }
         match statement {
             Statement::Expression(expr) => {
                 self.evaluate_expr(&expr)?;
             },
             Statement::Print(expr) => {
                 let expr = self.evaluate_expr(&expr)?;
-                println!("{}", expr);
+                writeln!(self.output, "{}", expr)?;
             },
-            Statement::VarDecl(ident, expr) => {
+            Statement::VarDecl(ident, expr, _) => {
                 let expr = self.evaluate_expr(&expr)?;
                 if let Node::Litteral(lit, _) = expr {
-                    self.environment.define(ident.clone(), lit);
+                    self.environment.borrow_mut().define(ident.clone(), lit);
                 }
             },
             Statement::Block(statements) => {
-                self.environment = Environment::with_parent(self.environment.clone());
-                
-                for stmnt in statements {
-                    self.execute(stmnt)?;
-                }
-
-                if let Some(parent) = self.environment.parent.clone() {
-                    self.environment = *parent;
-                } else {
-                    unreachable!();
-                }
+                let scope = Environment::with_parent(self.environment.clone()).scope();
+                return self.execute_block(statements, scope);
             },
             Statement::If(condition, body, else_body) => {
                 let result = self.evaluate_expr(&condition)?;
                 if let Node::Litteral(litteral, _) = result {
                     if litteral.is_truthy() {
-                        self.execute(*body)?;
-                    } else {
-                        if let Some(stmt) = else_body {
-                            self.execute(*stmt)?;
-                        }
+                        return self.execute(*body);
+                    } else if let Some(stmt) = else_body {
+                        return self.execute(*stmt);
                     }
-                } else {
                 }
             },
             Statement::While(condition, body) => {
@@ -67,7 +220,9 @@ impl Interpreter {
                     let result = self.evaluate_expr(&condition)?;
                     if let Node::Litteral(litteral, _) = result {
                         if litteral.is_truthy() {
-                            self.execute(*body.clone())?;
+                            if let Some(value) = self.execute(*body.clone())? {
+                                return Ok(Some(value));
+                            }
                         } else {
                             break;
                         }     
@@ -76,85 +231,154 @@ impl Interpreter {
             }
 
             Statement::For(init, con, inc, body) => {
-                if let Some(constructor) = init.clone() {
-                    match *constructor.clone() {
-                        Statement::VarDecl(ident, value) => {
-                            (ident, value)
-                        },
-                        _ => {
-                            eprintln!("Expected variable definition in for loop!");
-                            std::process::exit(70);
-                        }
+                let scope = Environment::with_parent(self.environment.clone()).scope();
+                let previous = std::mem::replace(&mut self.environment, scope);
+                let result = self.execute_for(init, con, inc, body);
+                self.environment = previous;
+                return result;
+            }
+            Statement::Function(function) => {
+                let name = function.name.clone();
+                let closure = Closure {
+                    declaration: Rc::new(function),
+                    closure: self.environment.clone(),
+                    is_initializer: false,
+                    chunk: None,
+                };
+                self.environment.borrow_mut().define(name, Litteral::Function(Rc::new(closure)));
+            },
+            Statement::Class(name, superclass, methods, _) => {
+                let superclass = match superclass {
+                    Some(Node::Identifier(ref parent, _, ref position)) if *parent == name => {
+                        return Err(runtime_error(
+                            diagnostics::INVALID_SUPERCLASS,
+                            "A class can't inherit from itself.",
+                            position
+                        ));
+                    },
+                    Some(node) => match self.evaluate_expr(&node)? {
+                        Node::Litteral(Litteral::Class(class), _) => Some(class),
+                        _ => return Err(runtime_error(
+                            diagnostics::INVALID_SUPERCLASS,
+                            "Superclass must be a class.",
+                            &node.position()
+                        )),
+                    },
+                    None => None,
+                };
+                let scope = match &superclass {
+                    Some(parent) => {
+                        let mut scope = Environment::with_parent(self.environment.clone());
+                        scope.define("super".to_string(), Litteral::Class(parent.clone()));
+                        scope.scope()
+                    },
+                    None => self.environment.clone(),
+                };
+                let methods = methods.into_iter().map(|method| {
+                    let closure = Closure {
+                        is_initializer: method.name == "init",
+                        declaration: Rc::new(method),
+                        closure: scope.clone(),
+                        chunk: None,
                     };
-                    self.execute(*constructor)?;
-                }
-                loop {
-                    if let Some(condition) = con.clone() {
-                        let result = self.evaluate_expr(&condition)?;
-                        if let Node::Litteral(litteral, _) = result {
-                            if litteral.is_truthy() {
-                                self.execute(*body.clone())?;
-                            } else {
-                                break;
-                            }     
-                        }
-                    }
-                    if let Some(increment) = inc.clone() {
-                        self.evaluate_expr(&increment)?;
-                    }
+                    (closure.declaration.name.clone(), Rc::new(closure))
+                }).collect();
+                let class = Class { name: name.clone(), superclass, methods };
+                self.environment.borrow_mut().define(name, Litteral::Class(Rc::new(class)));
+            },
+            Statement::Return(None, _) => return Ok(Some(Litteral::Nil)),
+            Statement::Return(Some(expr), _) => {
+                if let Node::Litteral(lit, _) = self.evaluate_expr(&expr)? {
+                    return Ok(Some(lit));
                 }
             }
 
         }
-        Ok(())
+        Ok(None)
     } 
+    /// Runs a `for` loop; the caller provides the scope its initializer lives in.
+    fn execute_for(
+        &mut self,
+        init: Option<Box<Statement>>,
+        con: Option<Node>,
+        inc: Option<Node>,
+        body: Box<Statement>
+    ) -> anyhow::Result<Option<Litteral>> {
+        if let Some(constructor) = init {
+            self.execute(*constructor)?;
+        }
+        loop {
+            if let Some(condition) = con.clone() {
+                let result = self.evaluate_expr(&condition)?;
+                if let Node::Litteral(litteral, _) = result {
+                    if !litteral.is_truthy() {
+                        break;
+                    }     
+                }
+            }
+            if let Some(value) = self.execute(*body.clone())? {
+                return Ok(Some(value));
+            }
+            if let Some(increment) = inc.clone() {
+                self.evaluate_expr(&increment)?;
+            }
+        }
+        Ok(None)
+    }
+    /// Runs `statements` inside `scope`, restoring the current scope afterwards
+    /// even if a statement fails.
+    fn execute_block(&mut self, statements: Vec<Statement>, scope: Scope) -> anyhow::Result<Option<Litteral>> {
+        let previous = std::mem::replace(&mut self.environment, scope);
+        let mut result = Ok(None);
+        for stmnt in statements {
+            result = self.execute(stmnt);
+            if !matches!(result, Ok(None)) {
+                break;
+            }
+        }
+        self.environment = previous;
+        result
+    }
+    /// Invokes `function` in a fresh frame whose parent is the scope it closed over.
+    fn call(&mut self, function: Rc<Closure>, arguments: Vec<Litteral>, position: &Position) -> anyhow::Result<Litteral> {
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(runtime_error(diagnostics::STACK_OVERFLOW, "Stack overflow.", position));
+        }
+        let mut frame = Environment::with_parent(function.closure.clone());
+        for (param, arg) in function.declaration.params.iter().zip(arguments) {
+            frame.define(param.clone(), arg);
+        }
+        if let Some(hook) = &mut self.hook {
+            hook.enter_call(&function.declaration, position, &self.environment);
+        }
+        self.depth += 1;
+        let returned = self.execute_block(function.declaration.body.clone(), frame.scope());
+        self.depth -= 1;
+        if let Some(hook) = &mut self.hook {
+            hook.exit_call();
+        }
+        let returned = returned?;
+        if function.is_initializer {
+            return function.closure.borrow().get(&"this".to_string());
+        }
+        Ok(returned.unwrap_or_default())
+    }
     pub fn evaluate_expr(&mut self, expr: &Node) -> anyhow::Result<Node> {
         match expr {
             Node::Binary { 
                 left, 
                 right, 
-                operator: BinaryOperator::Or,
-                position
+                operator: operator @ (BinaryOperator::Or | BinaryOperator::And),
+                ..
             } => {
                 let left = self.evaluate_expr(left)?;
-                if let Node::Litteral(l, _) = left.clone() {
-                    if l.is_truthy() {
+                if let Node::Litteral(l, _) = &left {
+                    if operator.short_circuits(l) {
                         return Ok(left);
                     }
-                    let right = self.evaluate_expr(right)?;
-                    if let Node::Litteral(r, _) = right {
-                        return Ok(
-                            Node::Litteral(
-                                BinaryOperator::Or.eval(l, r)?,
-                                position.clone()
-                            )
-                        );
-                    }
                 }
-                unreachable!()
-            },
-            Node::Binary { 
-                left, 
-                right, 
-                operator: BinaryOperator::And,
-                position
-            } => {
-                let left = self.evaluate_expr(left)?;
-                if let Node::Litteral(l, _) = left.clone() {
-                    if !l.is_truthy() {
-                        return Ok(left);
-                    }
-                    let right = self.evaluate_expr(right)?;
-                    if let Node::Litteral(r, _) = right {
-                        return Ok(
-                            Node::Litteral(
-                                BinaryOperator::And.eval(l, r)?,
-                                position.clone()
-                            )
-                        );
-                    }
-                }
-                unreachable!()
+                // Otherwise the result is the right operand, whatever it is.
+                self.evaluate_expr(right)
             },
             Node::Binary { left, right, operator, position } => {
                 let left = self.evaluate_expr(left)?;
@@ -163,10 +387,7 @@ impl Interpreter {
                 if let (Node::Litteral(l, _), Node::Litteral(r, _)) = (left, right) {
                     match operator.eval(l, r) {
                         Ok(lit) => Ok(Node::Litteral(lit, position.clone())),
-                        Err(e) => {
-                            eprintln!("{}", e);
-                            std::process::exit(70);
-                        }
+                        Err(e) => Err(runtime_error(diagnostics::OPERAND_TYPE, e, position)),
                     }
                 } else {
                     unreachable!();
@@ -175,15 +396,23 @@ impl Interpreter {
             Node::Parenthesis(node) => {
                 self.evaluate_expr(node)
             },
+            Node::Interpolation(parts, position) => {
+                let mut out = String::new();
+                for part in parts {
+                    if let Node::Litteral(value, _) = self.evaluate_expr(part)? {
+                        out.push_str(&value.to_string());
+                    } else {
+                        unreachable!();
+                    }
+                }
+                Ok(Node::Litteral(Litteral::String(out), position.clone()))
+            },
             Node::Unary(op, node, pos) => {
                 let node = self.evaluate_expr(node)?;
                 if let Node::Litteral(l, _) = node {
                     match op.eval(l) {
                         Ok(lit) => Ok(Node::Litteral(lit, pos.clone())),
-                        Err(e) => {
-                            eprintln!("{}", e);
-                            std::process::exit(70);
-                        }
+                        Err(e) => Err(runtime_error(diagnostics::OPERAND_TYPE, e, pos)),
                     }
                 } else {
                     unreachable!();
@@ -192,23 +421,127 @@ impl Interpreter {
             Node::Litteral(lit, pos) => {
                 Ok(Node::Litteral(lit.clone(), pos.clone()))
             },
-            Node::Identifier(i, pos) => {
-                match self.environment.get(i) {
+            Node::Identifier(i, depth, pos) => {
+                let value = match depth {
+                    Some(depth) => self.environment.borrow().get_at(*depth, i),
+                    None => self.global.borrow().get(i),
+                };
+                match value {
                     Ok(v) => Ok(Node::Litteral(v, pos.clone())),
-                    Err(e) => {
-                        eprintln!("{}", e);
-                        std::process::exit(70);
+                    Err(e) => Err(runtime_error(diagnostics::UNDEFINED_VARIABLE, e, pos)),
+                }
+            },
+            Node::Assignment(i, value, depth, pos) => {
+                let value = self.evaluate_expr(value)?;
+                let Node::Litteral(lit, _) = value.clone() else {
+                    unreachable!();
+                };
+                let assigned = match depth {
+                    Some(depth) => self.environment.borrow_mut().assign_at(*depth, i.clone(), lit),
+                    None => self.global.borrow_mut().assign(i.clone(), lit),
+                };
+                match assigned {
+                    Ok(()) => Ok(value),
+                    Err(e) => Err(runtime_error(diagnostics::UNDEFINED_VARIABLE, e, pos)),
+                }
+            },
+            Node::Call { callee, arguments, position } => {
+                let callee = match self.evaluate_expr(callee)? {
+                    Node::Litteral(lit, _) => lit,
+                    _ => unreachable!(),
+                };
+                let mut args: Vec<Litteral> = Vec::new();
+                for argument in arguments {
+                    if let Node::Litteral(lit, _) = self.evaluate_expr(argument)? {
+                        args.push(lit);
                     }
                 }
+                let arity = match &callee {
+                    Litteral::Function(function) => function.arity(),
+                    Litteral::Native(native) => native.arity,
+                    Litteral::Class(class) => class.arity(),
+                    _ => return Err(runtime_error(
+                        diagnostics::NOT_CALLABLE,
+                        "Can only call functions and classes.",
+                        position
+                    )),
+                };
+                if args.len() != arity {
+                    return Err(runtime_error(
+                        diagnostics::ARITY_MISMATCH,
+                        format!("Expected {} arguments but got {}.", arity, args.len()),
+                        position
+                    ));
+                }
+                let value = match callee {
+                    Litteral::Function(function) => self.call(function, args, position)?,
+                    Litteral::Native(native) => match (native.function)(&args) {
+                        Ok(value) => value,
+                        Err(e) => return Err(runtime_error(diagnostics::NATIVE_ERROR, e, position)),
+                    },
+                    Litteral::Class(class) => {
+                        let instance = Rc::new(RefCell::new(Instance {
+                            class: class.clone(),
+                            fields: HashMap::new(),
+                        }));
+                        if let Some(init) = class.find_method("init") {
+                            self.call(Rc::new(init.bind(instance.clone())), args, position)?;
+                        }
+                        Litteral::Instance(instance)
+                    },
+                    _ => unreachable!(),
+                };
+                Ok(Node::Litteral(value, position.clone()))
+            },
+            Node::Get(object, name, position) => {
+                let Node::Litteral(Litteral::Instance(instance), _) = self.evaluate_expr(object)? else {
+                    return Err(runtime_error(diagnostics::NOT_AN_INSTANCE, "Only instances have properties.", position));
+                };
+                match Instance::get(&instance, name) {
+                    Some(value) => Ok(Node::Litteral(value, position.clone())),
+                    None => Err(runtime_error(
+                        diagnostics::UNDEFINED_PROPERTY,
+                        format!("Undefined property '{}'.", name),
+                        position
+                    )),
+                }
             },
-            Node::Assignment(i, value, _) => {
+            Node::Set(object, name, value, position) => {
+                let Node::Litteral(Litteral::Instance(instance), _) = self.evaluate_expr(object)? else {
+                    return Err(runtime_error(diagnostics::NOT_AN_INSTANCE, "Only instances have fields.", position));
+                };
                 let value = self.evaluate_expr(value)?;
                 if let Node::Litteral(lit, _) = value.clone() {
-                    self.environment.assign(i.clone(), lit)?;
-                    Ok(value)
-                } else {
-                    eprintln!("Unknown variable type!");
-                    std::process::exit(70);
+                    instance.borrow_mut().fields.insert(name.clone(), lit);
+                }
+                Ok(value)
+            },
+            Node::This(position) => {
+                match self.environment.borrow().get(&"this".to_string()) {
+                    Ok(v) => Ok(Node::Litteral(v, position.clone())),
+                    Err(e) => Err(runtime_error(diagnostics::INVALID_THIS, e, position)),
+                }
+            },
+            Node::Super(method, position) => {
+                let superclass = self.environment.borrow().get(&"super".to_string());
+                let this = self.environment.borrow().get(&"this".to_string());
+                let (Ok(Litteral::Class(superclass)), Ok(Litteral::Instance(instance))) = (superclass, this) else {
+                    return Err(runtime_error(
+                        diagnostics::INVALID_SUPER,
+                        "Can't use 'super' outside of a subclass method.",
+                        position
+                    ));
+                };
+                match superclass.find_method(method) {
+                    Some(found) => Ok(Node::Litteral(
+                        Litteral::Function(Rc::new(found.bind(instance))),
+                        position.clone()
+                    )),
+                    None => Err(runtime_error(
+                        diagnostics::UNDEFINED_PROPERTY,
+                        format!("Undefined property '{}'.", method),
+                        position
+                    )),
                 }
             }
         }
@@ -216,13 +549,23 @@ impl Interpreter {
 }
 
 impl BinaryOperator {
-    fn eval(&self, left: Litteral, right: Litteral) -> anyhow::Result<Litteral> {
+    /// Whether `and`/`or` produce `left` without looking at their right
+    /// operand. When they don't, the result is the right operand.
+    pub fn short_circuits(&self, left: &Litteral) -> bool {
+        match self {
+            BinaryOperator::Or => left.is_truthy(),
+            BinaryOperator::And => !left.is_truthy(),
+            _ => false,
+        }
+    }
+    pub fn eval(&self, left: Litteral, right: Litteral) -> anyhow::Result<Litteral> {
         use Litteral::*;
         use BinaryOperator::*;
 
         match (left.clone(), self, right.clone()) {
-            (Number(l), Eq,  Number(r)) => Ok(Boolean(l == r)),
-            (Number(l), NEq,  Number(r)) => Ok(Boolean(l != r)),
+            (l, Eq, r) => Ok(Boolean(l.is_equal(&r))),
+            (l, NEq, r) => Ok(Boolean(!l.is_equal(&r))),
+
             (Number(l), LEq,  Number(r)) => Ok(Boolean(l <= r)),
             (Number(l), GEq,  Number(r)) => Ok(Boolean(l >= r)),
             (Number(l), L,  Number(r)) => Ok(Boolean(l < r)),
@@ -234,40 +577,34 @@ impl BinaryOperator {
             (Number(l), Div,  Number(r)) => Ok(Number(l / r)),
             (Number(l), Pow,  Number(r)) => Ok(Number(l.powf(r))),
 
-            (String(l), Eq, String(r)) => Ok(Boolean(l == r)),
-            (String(l), NEq, String(r)) => Ok(Boolean(l != r)),
             (String(l), Add, String(r)) => Ok(String(format!("{}{}", l, r))),
 
-            (Boolean(l), Eq, Boolean(r)) => Ok(Boolean(l == r)),
-            (Boolean(l), NEq, Boolean(r)) => Ok(Boolean(l != r)),
-
-            (String(_), Eq, Number(_)) => Ok(Boolean(false)),
-            (Number(_), Eq, String(_)) => Ok(Boolean(false)),
-
-            (Boolean(true), Or, _) => Ok(Boolean(true)),
-            (Number(l), Or, _) => Ok(Number(l)),
-            (String(l), Or, _) => Ok(String(l)),
-            (_, Or, Boolean(true)) => Ok(Boolean(true)),
-            (_, Or, String(r)) => Ok(String(r)),
-            (_, Or, Number(r)) => Ok(Number(r)),
-            (_, Or, _) => Ok(Boolean(false)),
-
-            (Boolean(false) | Nil, And, _) => Ok(Boolean(false)),
-            (_, And, Boolean(false) | Nil) => Ok(Boolean(false)),
-            (Boolean(true), And, Boolean(true)) => Ok(Boolean(true)),
-            (l, And, r) if l.is_truthy() && r.is_truthy() => Ok(r),
-            (_, And, _) => Ok(Boolean(false)),
-
+            (_, Or | And, _) => unreachable!("`and` and `or` short-circuit instead"),
 
             (String(_), Add, Number(_)) |
             (Number(_), Add, String(_)) => Err(anyhow!("Operands must be two numbers or two strings")),
             (_, Add | Sub | Mul | Div | Pow, _) => Err(anyhow!("Operands must be numbers")),
-            (_, Eq | NEq | LEq | GEq | L | G, _) => Err(anyhow!("Operands must be numbers")),
+            (_, LEq | GEq | L | G, _) => Err(anyhow!("Operands must be numbers")),
         }
     }
 }
 
 impl Litteral {
+    /// `==` in Lox: values of different types are never equal, and functions,
+    /// classes and instances are only equal to themselves.
+    pub fn is_equal(&self, other: &Litteral) -> bool {
+        match (self, other) {
+            (Self::Number(l), Self::Number(r)) => l == r,
+            (Self::Boolean(l), Self::Boolean(r)) => l == r,
+            (Self::Nil, Self::Nil) => true,
+            (Self::String(l), Self::String(r)) => l == r,
+            (Self::Function(l), Self::Function(r)) => Rc::ptr_eq(l, r),
+            (Self::Native(l), Self::Native(r)) => Rc::ptr_eq(l, r),
+            (Self::Class(l), Self::Class(r)) => Rc::ptr_eq(l, r),
+            (Self::Instance(l), Self::Instance(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
     pub fn is_truthy(&self) -> bool {
         match self {
             Self::Boolean(true) => true,
@@ -275,12 +612,16 @@ impl Litteral {
             Self::Nil => false,
             Self::String(_) => true,
             Self::Number(_) => true,
+            Self::Function(_) => true,
+            Self::Native(_) => true,
+            Self::Class(_) => true,
+            Self::Instance(_) => true,
         }
     }
 }
 
 impl UnaryOperator {
-    fn eval(&self, lit: Litteral) -> anyhow::Result<Litteral> {
+    pub fn eval(&self, lit: Litteral) -> anyhow::Result<Litteral> {
         use Litteral::*;
         use UnaryOperator::*;
 
@@ -293,3 +634,9 @@ impl UnaryOperator {
         }
     }
 }
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
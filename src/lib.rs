@@ -0,0 +1,55 @@
+pub mod chunk;
+pub mod compiler;
+pub mod dap;
+pub mod debugger;
+pub mod diagnostics;
+pub mod disassembler;
+pub mod environment;
+pub mod error;
+pub mod formatter;
+pub mod interpreter;
+pub mod linter;
+pub mod lsp;
+pub mod optimizer;
+pub mod lox;
+pub mod parser;
+pub mod position;
+pub mod protocol;
+pub mod repl;
+pub mod resolver;
+pub mod scanner;
+pub mod shell;
+pub mod source_map;
+pub mod vm;
+
+pub use error::LoxError;
+pub use interpreter::Interpreter;
+pub use lox::{Capture, Lox};
+pub use parser::Litteral;
+
+use diagnostics::Diagnostic;
+use parser::{AstFactory, Statement};
+use resolver::Resolver;
+use scanner::Token;
+use source_map::FileId;
+
+/// Scans, parses and resolves `source` into statements ready to run.
+pub fn compile(source: &str) -> Result<Vec<Statement>, LoxError> {
+    compile_file(FileId::default(), source)
+}
+
+/// Like `compile`, with positions pointing into `file`.
+pub fn compile_file(file: FileId, source: &str) -> Result<Vec<Statement>, LoxError> {
+    let tokens: Vec<Token> = scanner::scan_file(file, source.to_string());
+    let invalid: Vec<Diagnostic> = tokens.iter().filter_map(Token::diagnostic).collect();
+    if !invalid.is_empty() {
+        return Err(LoxError::ScanError(invalid));
+    }
+    let mut ast: AstFactory = AstFactory::new(tokens);
+    let (mut statements, errors) = ast.parse_statements();
+    if !errors.is_empty() {
+        return Err(LoxError::ParseError(errors));
+    }
+    Resolver::new().resolve(&mut statements).map_err(LoxError::ParseError)?;
+    Ok(statements)
+}
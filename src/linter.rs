@@ -0,0 +1,346 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use crate::{
+    diagnostics::{self, Diagnostic},
+    parser::{BinaryOperator, Function, Litteral, Node, Statement},
+    position::Position,
+    source_map::SourceMap,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnusedVariable,
+    ShadowedDeclaration,
+    UnreachableCode,
+    AssignmentInCondition,
+    NilComparison,
+    ConstantCondition,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedVariable,
+        Rule::ShadowedDeclaration,
+        Rule::UnreachableCode,
+        Rule::AssignmentInCondition,
+        Rule::NilComparison,
+        Rule::ConstantCondition,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::ShadowedDeclaration => "shadowed-declaration",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::AssignmentInCondition => "assignment-in-condition",
+            Rule::NilComparison => "nil-comparison",
+            Rule::ConstantCondition => "constant-condition",
+        }
+    }
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => diagnostics::UNUSED_VARIABLE,
+            Rule::ShadowedDeclaration => diagnostics::SHADOWED_DECLARATION,
+            Rule::UnreachableCode => diagnostics::UNREACHABLE_CODE,
+            Rule::AssignmentInCondition => diagnostics::ASSIGNMENT_IN_CONDITION,
+            Rule::NilComparison => diagnostics::NIL_COMPARISON,
+            Rule::ConstantCondition => diagnostics::CONSTANT_CONDITION,
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::ALL.into_iter()
+            .find(|rule| rule.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Rule::ALL.iter().map(Rule::name).collect();
+                format!("unknown lint rule '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+/// A name declared in some scope, and whether anything reads it.
+struct Binding {
+    name: String,
+    position: Position,
+    /// Only `var` declarations are reported when unused.
+    variable: bool,
+    used: bool,
+}
+
+/// Static checks over a resolved program that report suspicious but valid
+/// code as warnings. Every rule is enabled by default.
+pub struct Linter {
+    rules: Vec<Rule>,
+    bindings: Vec<Binding>,
+    scopes: Vec<HashMap<String, usize>>,
+    /// Reads of names not declared yet; they can only be globals declared
+    /// further down, so they are looked up once the program has been walked.
+    unresolved: Vec<String>,
+    warnings: Vec<Diagnostic>,
+    /// Shadowing warnings by index, with the name and outer declaration they
+    /// point back to. Their notes need the source, so they are added last.
+    shadowed: Vec<(usize, String, Position)>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self {
+            rules: Rule::ALL.to_vec(),
+            bindings: Vec::new(),
+            scopes: Vec::new(),
+            unresolved: Vec::new(),
+            warnings: Vec::new(),
+            shadowed: Vec::new(),
+        }
+    }
+    pub fn enable(&mut self, rule: Rule) {
+        if !self.rules.contains(&rule) {
+            self.rules.push(rule);
+        }
+    }
+    pub fn disable(&mut self, rule: Rule) {
+        self.rules.retain(|enabled| *enabled != rule);
+    }
+    /// Returns the warnings for `statements`, in source order. `sources`
+    /// holds the files they were compiled from.
+    pub fn lint(&mut self, statements: &[Statement], sources: &SourceMap) -> Vec<Diagnostic> {
+        self.scopes.push(HashMap::new());
+        self.statements(statements);
+        for name in std::mem::take(&mut self.unresolved) {
+            if let Some(&binding) = self.scopes[0].get(&name) {
+                self.bindings[binding].used = true;
+            }
+        }
+        self.end_scope();
+        self.bindings.clear();
+        for (index, name, outer) in std::mem::take(&mut self.shadowed) {
+            let note = match sources.get(outer.file) {
+                Some(file) => format!("'{}' is first declared on line {}", name, file.line(outer.start)),
+                None => format!("'{}' is first declared further out", name),
+            };
+            self.warnings[index].notes.insert(0, note);
+        }
+        let mut warnings = std::mem::take(&mut self.warnings);
+        warnings.sort_by_key(|warning| (warning.span.file, warning.span.start));
+        warnings
+    }
+    fn warn(&mut self, rule: Rule, message: impl Into<String>, span: Position) -> Option<&mut Diagnostic> {
+        if !self.rules.contains(&rule) {
+            return None;
+        }
+        let warning = Diagnostic::warning(rule.code(), message, span)
+            .with_note(format!("'{}' is on by default", rule));
+        self.warnings.push(warning);
+        self.warnings.last_mut()
+    }
+    fn declare(&mut self, name: &str, position: &Position, variable: bool) {
+        let shadowed = self.scopes[..self.scopes.len() - 1].iter().rev()
+            .find_map(|scope| scope.get(name))
+            .map(|&binding| self.bindings[binding].position.clone());
+        if let Some(outer) = shadowed {
+            let message = format!("Declaration of '{}' shadows an outer one.", name);
+            if self.warn(Rule::ShadowedDeclaration, message, position.clone()).is_some() {
+                self.shadowed.push((self.warnings.len() - 1, name.to_string(), outer));
+            }
+        }
+        self.bindings.push(Binding {
+            name: name.to_string(),
+            position: position.clone(),
+            variable,
+            used: false,
+        });
+        let binding = self.bindings.len() - 1;
+        self.scopes.last_mut().unwrap().insert(name.to_string(), binding);
+    }
+    fn read(&mut self, name: &str) {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(&binding) => self.bindings[binding].used = true,
+            None => self.unresolved.push(name.to_string()),
+        }
+    }
+    /// Pops the innermost scope and reports its unused variables.
+    fn end_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        let unused: Vec<usize> = scope.into_values()
+            .filter(|&binding| self.bindings[binding].variable && !self.bindings[binding].used)
+            .filter(|&binding| !self.bindings[binding].name.starts_with('_'))
+            .collect();
+        for binding in unused {
+            let Binding { name, position, .. } = &self.bindings[binding];
+            let (message, position) = (format!("Unused variable '{}'.", name), position.clone());
+            let help = format!("prefix it with an underscore if this is intentional: '_{}'", name);
+            if let Some(warning) = self.warn(Rule::UnusedVariable, message, position) {
+                warning.help = Some(help);
+            }
+        }
+    }
+    fn statements(&mut self, statements: &[Statement]) {
+        let unreachable = statements.iter()
+            .position(returns)
+            .and_then(|index| statements.get(index + 1))
+            .and_then(Statement::position);
+        if let Some(position) = unreachable {
+            if let Some(warning) = self.warn(Rule::UnreachableCode, "Unreachable code.", position) {
+                warning.notes.insert(0, "the code before it always returns".to_string());
+            }
+        }
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expr) | Statement::Print(expr) | Statement::Return(Some(expr), _) => self.expr(expr),
+            Statement::Return(None, _) => {},
+            Statement::VarDecl(name, initializer, position) => {
+                self.expr(initializer);
+                self.declare(name, position, true);
+            },
+            Statement::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                self.statements(statements);
+                self.end_scope();
+            },
+            Statement::If(condition, then, els) => {
+                if let Node::Assignment(.., position) | Node::Set(.., position) = condition {
+                    let message = "Assignment used as an 'if' condition.";
+                    if let Some(warning) = self.warn(Rule::AssignmentInCondition, message, position.clone()) {
+                        warning.help = Some("use '==' to compare, or wrap the assignment in parentheses".to_string());
+                    }
+                }
+                self.expr(condition);
+                self.statement(then);
+                if let Some(els) = els {
+                    self.statement(els);
+                }
+            },
+            Statement::While(condition, body) => {
+                if let Some(value) = constant(condition) {
+                    let truthy = value.is_truthy();
+                    let message = format!("This 'while' condition is always {}.", truthy);
+                    if let Some(warning) = self.warn(Rule::ConstantCondition, message, condition.position()) {
+                        warning.help = Some(match truthy {
+                            true => "use 'for (;;)' for a loop that is meant to run forever".to_string(),
+                            false => "the loop body never runs and can be removed".to_string(),
+                        });
+                    }
+                }
+                self.expr(condition);
+                self.statement(body);
+            },
+            Statement::For(init, condition, increment, body) => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                condition.iter().chain(increment).for_each(|expr| self.expr(expr));
+                self.statement(body);
+                self.end_scope();
+            },
+            Statement::Function(function) => {
+                self.declare(&function.name, &function.position, false);
+                self.function(function);
+            },
+            Statement::Class(name, superclass, methods, position) => {
+                self.declare(name, position, false);
+                if let Some(superclass) = superclass {
+                    self.expr(superclass);
+                }
+                for method in methods {
+                    self.function(method);
+                }
+            },
+        }
+    }
+    fn function(&mut self, function: &Function) {
+        self.scopes.push(HashMap::new());
+        for (param, position) in function.params.iter().zip(&function.param_positions) {
+            self.declare(param, position, false);
+        }
+        self.statements(&function.body);
+        self.end_scope();
+    }
+    fn expr(&mut self, expr: &Node) {
+        match expr {
+            Node::Binary { left, right, operator, position } => {
+                let nil = |node: &Node| matches!(strip(node), Node::Litteral(Litteral::Nil, _));
+                let never_nil = |node: &Node| !nil(node) && constant(node).is_some();
+                let never_equal = (nil(left) && never_nil(right)) || (never_nil(left) && nil(right));
+                let message = match operator {
+                    BinaryOperator::L | BinaryOperator::G | BinaryOperator::LEq | BinaryOperator::GEq
+                        if nil(left) || nil(right) => Some("Ordering 'nil' is always a runtime error."),
+                    BinaryOperator::Eq if never_equal => Some("Comparison with 'nil' is always false."),
+                    BinaryOperator::NEq if never_equal => Some("Comparison with 'nil' is always true."),
+                    _ => None,
+                };
+                if let Some(message) = message {
+                    self.warn(Rule::NilComparison, message, position.clone());
+                }
+                self.expr(left);
+                self.expr(right);
+            },
+            Node::Parenthesis(inner) | Node::Unary(_, inner, _) => self.expr(inner),
+            Node::Litteral(_, _) | Node::This(_) | Node::Super(_, _) => {},
+            Node::Identifier(name, _, _) => self.read(name),
+            Node::Assignment(_, value, _, _) => self.expr(value),
+            Node::Call { callee, arguments, .. } => {
+                self.expr(callee);
+                arguments.iter().for_each(|argument| self.expr(argument));
+            },
+            Node::Interpolation(parts, _) => parts.iter().for_each(|part| self.expr(part)),
+            Node::Get(object, _, _) => self.expr(object),
+            Node::Set(object, _, value, _) => {
+                self.expr(value);
+                self.expr(object);
+            },
+        }
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether control never continues past `statement`.
+fn returns(statement: &Statement) -> bool {
+    match statement {
+        Statement::Return(..) => true,
+        Statement::Block(statements) => statements.iter().any(returns),
+        Statement::If(_, then, Some(els)) => returns(then) && returns(els),
+        _ => false,
+    }
+}
+
+fn strip(node: &Node) -> &Node {
+    match node {
+        Node::Parenthesis(inner) => strip(inner),
+        node => node,
+    }
+}
+
+/// The value of an expression made only of literals, if it has one.
+fn constant(node: &Node) -> Option<Litteral> {
+    match node {
+        Node::Litteral(value, _) => Some(value.clone()),
+        Node::Parenthesis(inner) => constant(inner),
+        Node::Unary(operator, operand, _) => operator.eval(constant(operand)?).ok(),
+        Node::Binary { left, right, operator: operator @ (BinaryOperator::Or | BinaryOperator::And), .. } => {
+            let left = constant(left)?;
+            if operator.short_circuits(&left) { Some(left) } else { constant(right) }
+        },
+        Node::Binary { left, right, operator, .. } => operator.eval(constant(left)?, constant(right)?).ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,121 @@
+use std::{cell::RefCell, io::Write, path::Path, rc::Rc};
+
+use crate::{
+    compile_file,
+    error::LoxError,
+    interpreter::Interpreter,
+    parser::{Litteral, Statement},
+    source_map::SourceMap,
+};
+
+/// Embedding entry point: one interpreter whose globals persist across
+/// calls to `eval_str` and `run_file`.
+pub struct Lox {
+    interpreter: Interpreter,
+    /// Every source compiled so far, since functions declared by one can
+    /// fail while running another.
+    sources: SourceMap,
+}
+
+/// An output sink collecting everything printed, returned by
+/// `Lox::capture_output`.
+#[derive(Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Lox {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+            sources: SourceMap::new(),
+        }
+    }
+    pub fn with_output(output: impl Write + 'static) -> Self {
+        Self {
+            interpreter: Interpreter::with_output(Box::new(output)),
+            sources: SourceMap::new(),
+        }
+    }
+    /// Starts a fresh interpreter whose `print` output is kept in memory.
+    pub fn capture_output() -> (Self, Capture) {
+        let capture = Capture::default();
+        (Self::with_output(capture.clone()), capture)
+    }
+    /// Runs `source` and returns the value of its last statement when that
+    /// is a bare expression, `nil` otherwise.
+    pub fn eval_str(&mut self, source: &str) -> Result<Litteral, LoxError> {
+        let statements = self.compile("<eval>", source)?;
+        Ok(self.eval_statements(statements)?.unwrap_or_default())
+    }
+    /// Adds `source` to the session's `SourceMap` under `name` and compiles it.
+    pub fn compile(&mut self, name: &str, source: &str) -> Result<Vec<Statement>, LoxError> {
+        let file = self.sources.add(name, source);
+        compile_file(file, source)
+    }
+    /// Runs already compiled statements. Returns `Some` with the value of the
+    /// last statement when that is a bare expression.
+    pub fn eval_statements(&mut self, mut statements: Vec<Statement>) -> Result<Option<Litteral>, LoxError> {
+        let last = match statements.last() {
+            Some(Statement::Expression(_)) => statements.pop(),
+            _ => None,
+        };
+        self.interpreter.interpret(statements)?;
+        match last {
+            Some(Statement::Expression(expr)) => Ok(Some(self.interpreter.evaluate(&expr)?)),
+            _ => Ok(None),
+        }
+    }
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<(), LoxError> {
+        let source = std::fs::read_to_string(&path)?;
+        let statements = self.compile(&path.as_ref().display().to_string(), &source)?;
+        self.interpreter.interpret(statements)
+    }
+    pub fn get_global(&self, name: &str) -> Option<Litteral> {
+        self.interpreter.globals().borrow().variables.get(name).cloned()
+    }
+    pub fn define_global(&mut self, name: &str, value: Litteral) {
+        self.interpreter.globals().borrow_mut().define(name.to_string(), value);
+    }
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Litteral]) -> anyhow::Result<Litteral> + 'static
+    ) {
+        self.interpreter.define_native(name, arity, function);
+    }
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+    /// The sources compiled so far, for rendering errors against.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+}
+
+impl Capture {
+    /// Everything printed so far.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+    /// Returns everything printed so far and clears the buffer.
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.0.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    compile,
+    parser::{AstFactory, Function, Node, Statement},
+    position::Position,
+    protocol::{read_message, write_message},
+    scanner::{self, Token, TokenType},
+    source_map::SourceFile,
+};
+
+/// Semantic token types, in the order advertised to the client.
+const TOKEN_TYPES: [&str; 5] = ["keyword", "variable", "string", "number", "operator"];
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Variable,
+    Function,
+    Class,
+    Method,
+    Parameter,
+}
+
+/// A declared name, located by the span of the name itself.
+struct Declaration {
+    name: String,
+    position: Position,
+    /// One-line signature shown on hover.
+    detail: String,
+}
+
+/// Declarations and name references of one document, built by walking the
+/// AST with the same scoping rules as the resolver.
+struct Index {
+    file: SourceFile,
+    declarations: Vec<Declaration>,
+    /// Each reference and the declaration it resolves to.
+    references: Vec<(Position, String, usize)>,
+    symbols: Vec<Value>,
+    scopes: Vec<HashMap<String, usize>>,
+    /// References to names not declared yet, resolved against the globals
+    /// once the whole document has been seen.
+    unresolved: Vec<(Position, String)>,
+}
+
+/// Runs a Language Server Protocol server on `input`/`output` until the
+/// client sends `exit`.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> anyhow::Result<()> {
+    let mut documents: HashMap<String, String> = HashMap::new();
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "documentSymbolProvider": true,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "semanticTokensProvider": {
+                        "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                        "full": true,
+                    },
+                },
+                "serverInfo": { "name": "lox" },
+            }),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = match method {
+                    "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+                    _ => params["contentChanges"].as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str()),
+                };
+                let text = text.unwrap_or_default().to_string();
+                let diagnostics = diagnostics(&text);
+                documents.insert(uri.clone(), text);
+                write_message(&mut output, &json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": diagnostics },
+                }))?;
+                continue;
+            },
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+                continue;
+            },
+            "textDocument/documentSymbol" => {
+                let source = documents.get(&uri).map_or("", String::as_str);
+                Value::Array(Index::build(source).symbols)
+            },
+            "textDocument/definition" => {
+                let source = documents.get(&uri).map_or("", String::as_str);
+                let index = Index::build(source);
+                match index.find(&params["position"]) {
+                    Some(declaration) => json!({
+                        "uri": uri,
+                        "range": range(&index.file, &declaration.position, &declaration.name),
+                    }),
+                    None => Value::Null,
+                }
+            },
+            "textDocument/hover" => {
+                let source = documents.get(&uri).map_or("", String::as_str);
+                let index = Index::build(source);
+                match index.find(&params["position"]) {
+                    Some(declaration) => json!({
+                        "contents": { "kind": "markdown", "value": format!("```lox\n{}\n```", declaration.detail) },
+                    }),
+                    None => Value::Null,
+                }
+            },
+            "textDocument/semanticTokens/full" => {
+                let source = documents.get(&uri).map_or("", String::as_str);
+                json!({ "data": semantic_tokens(source) })
+            },
+            "shutdown" => Value::Null,
+            "exit" => return Ok(()),
+            _ if message.get("id").is_none() => continue,
+            _ => {
+                write_message(&mut output, &json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": { "code": -32601, "message": format!("Unsupported method '{}'.", method) },
+                }))?;
+                continue;
+            },
+        };
+        write_message(&mut output, &json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }))?;
+    }
+    Ok(())
+}
+
+/// LSP location of a byte `offset`: 0-based, with columns in UTF-16 units.
+fn location(file: &SourceFile, offset: usize) -> Value {
+    let (line, column) = file.line_col_utf16(offset);
+    json!({ "line": line - 1, "character": column - 1 })
+}
+
+/// Range of `name` starting at the beginning of `position`.
+fn range(file: &SourceFile, position: &Position, name: &str) -> Value {
+    json!({
+        "start": location(file, position.start),
+        "end": location(file, position.start + name.len()),
+    })
+}
+
+fn diagnostics(source: &str) -> Vec<Value> {
+    let Err(error) = compile(source) else {
+        return Vec::new();
+    };
+    let file = SourceFile::new("", source);
+    if error.diagnostics().is_empty() {
+        return vec![json!({
+            "range": { "start": location(&file, 0), "end": location(&file, 0) },
+            "severity": 1,
+            "message": error.to_string(),
+        })];
+    }
+    error.diagnostics().iter().map(|diagnostic| {
+        let span = &diagnostic.span;
+        let mut message = diagnostic.message.clone();
+        for note in &diagnostic.notes {
+            message.push_str(&format!("\nnote: {}", note));
+        }
+        if let Some(help) = &diagnostic.help {
+            message.push_str(&format!("\nhelp: {}", help));
+        }
+        json!({
+            "range": {
+                "start": location(&file, span.start),
+                "end": location(&file, span.end.max(span.start + 1)),
+            },
+            "severity": 1,
+            "code": diagnostic.code,
+            "source": "lox",
+            "message": message,
+        })
+    }).collect()
+}
+
+/// Delta-encoded semantic tokens for every single-line token in `source`.
+fn semantic_tokens(source: &str) -> Vec<u32> {
+    let tokens = scanner::scan(source.to_string());
+    let file = SourceFile::new("", source);
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for Token { token_type, raw, position } in tokens {
+        let token_type = match token_type {
+            TokenType::If
+            | TokenType::And
+            | TokenType::Class
+            | TokenType::Else
+            | TokenType::False
+            | TokenType::For
+            | TokenType::Fun
+            | TokenType::Nil
+            | TokenType::Or
+            | TokenType::Print
+            | TokenType::Return
+            | TokenType::Super
+            | TokenType::This
+            | TokenType::True
+            | TokenType::Var
+            | TokenType::While => 0,
+            TokenType::Identifier(_) => 1,
+            TokenType::StringLitteral(_) | TokenType::Interpolation(_) => 2,
+            TokenType::Number(_) => 3,
+            TokenType::Invalid(_) => continue,
+            _ if raw.chars().all(|c| "+-*/^=!<>".contains(c)) => 4,
+            _ => continue,
+        };
+        let (line, column) = file.line_col_utf16(position.start);
+        if file.line(position.end) != line {
+            continue;
+        }
+        let (line, start) = (line as u32 - 1, column as u32 - 1);
+        let delta_start = if line == previous_line { start - previous_start } else { start };
+        data.extend([line - previous_line, delta_start, raw.encode_utf16().count() as u32, token_type, 0]);
+        (previous_line, previous_start) = (line, start);
+    }
+    data
+}
+
+impl Index {
+    fn build(source: &str) -> Self {
+        let mut index = Index {
+            file: SourceFile::new("", source),
+            declarations: Vec::new(),
+            references: Vec::new(),
+            symbols: Vec::new(),
+            scopes: Vec::new(),
+            unresolved: Vec::new(),
+        };
+        let tokens = scanner::scan(source.to_string());
+        let (statements, _) = AstFactory::new(tokens).parse_statements();
+        index.scopes.push(HashMap::new());
+        index.symbols = index.statements(&statements);
+        for (position, name) in std::mem::take(&mut index.unresolved) {
+            if let Some(&declaration) = index.scopes[0].get(&name) {
+                index.references.push((position, name, declaration));
+            }
+        }
+        index
+    }
+    /// The declaration referenced or declared at an LSP `position`.
+    fn find(&self, position: &Value) -> Option<&Declaration> {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        let offset = self.file.offset_utf16(line + 1, character + 1);
+        let contains = |at: &Position, name: &str| at.start <= offset && offset <= at.start + name.len();
+        if let Some((_, _, declaration)) = self.references.iter().find(|(at, name, _)| contains(at, name)) {
+            return Some(&self.declarations[*declaration]);
+        }
+        self.declarations.iter().find(|d| contains(&d.position, &d.name))
+    }
+    fn declare(&mut self, name: &str, kind: Kind, position: &Position, detail: String) -> Value {
+        self.declarations.push(Declaration {
+            name: name.to_string(),
+            position: position.clone(),
+            detail,
+        });
+        let index = self.declarations.len() - 1;
+        // Methods are only reachable through `this.` or an instance, so their
+        // names never shadow anything in scope.
+        if !matches!(kind, Kind::Method) {
+            self.scopes.last_mut().unwrap().insert(name.to_string(), index);
+        }
+        let kind = match kind {
+            Kind::Variable | Kind::Parameter => 13,
+            Kind::Function => 12,
+            Kind::Class => 5,
+            Kind::Method => 6,
+        };
+        json!({
+            "name": name,
+            "kind": kind,
+            "range": range(&self.file, position, name),
+            "selectionRange": range(&self.file, position, name),
+        })
+    }
+    fn reference(&mut self, name: &str, position: &Position) {
+        let found = self.scopes.iter().rev().find_map(|scope| scope.get(name).copied());
+        match found {
+            Some(declaration) => self.references.push((position.clone(), name.to_string(), declaration)),
+            None => self.unresolved.push((position.clone(), name.to_string())),
+        }
+    }
+    fn statements(&mut self, statements: &[Statement]) -> Vec<Value> {
+        statements.iter().flat_map(|statement| self.statement(statement)).collect()
+    }
+    /// Indexes `statement` and returns the document symbols it declares.
+    fn statement(&mut self, statement: &Statement) -> Vec<Value> {
+        match statement {
+            Statement::Expression(expr) | Statement::Print(expr) | Statement::Return(Some(expr), _) => {
+                self.expr(expr);
+                Vec::new()
+            },
+            Statement::Return(None, _) => Vec::new(),
+            Statement::VarDecl(name, initializer, position) => {
+                self.expr(initializer);
+                vec![self.declare(name, Kind::Variable, position, format!("var {}", name))]
+            },
+            Statement::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                let symbols = self.statements(statements);
+                self.scopes.pop();
+                symbols
+            },
+            Statement::If(condition, then, els) => {
+                self.expr(condition);
+                let mut symbols = self.statement(then);
+                if let Some(els) = els {
+                    symbols.extend(self.statement(els));
+                }
+                symbols
+            },
+            Statement::While(condition, body) => {
+                self.expr(condition);
+                self.statement(body)
+            },
+            Statement::For(init, condition, increment, body) => {
+                self.scopes.push(HashMap::new());
+                let mut symbols = init.as_ref().map(|init| self.statement(init)).unwrap_or_default();
+                condition.iter().chain(increment).for_each(|expr| self.expr(expr));
+                symbols.extend(self.statement(body));
+                self.scopes.pop();
+                symbols
+            },
+            Statement::Function(function) => vec![self.function(function, Kind::Function)],
+            Statement::Class(name, superclass, methods, position) => {
+                let detail = match superclass {
+                    Some(Node::Identifier(superclass, _, _)) => format!("class {} < {}", name, superclass),
+                    _ => format!("class {}", name),
+                };
+                let mut symbol = self.declare(name, Kind::Class, position, detail);
+                if let Some(superclass) = superclass {
+                    self.expr(superclass);
+                }
+                let methods: Vec<Value> = methods.iter().map(|method| self.function(method, Kind::Method)).collect();
+                symbol["children"] = Value::Array(methods);
+                vec![symbol]
+            },
+        }
+    }
+    fn function(&mut self, function: &Function, kind: Kind) -> Value {
+        let signature = format!("{}({})", function.name, function.params.join(", "));
+        let detail = match kind {
+            Kind::Method => signature,
+            _ => format!("fun {}", signature),
+        };
+        let mut symbol = self.declare(&function.name, kind, &function.position, detail);
+        self.scopes.push(HashMap::new());
+        for (param, position) in function.params.iter().zip(&function.param_positions) {
+            self.declare(param, Kind::Parameter, position, format!("(parameter) {}", param));
+        }
+        symbol["children"] = Value::Array(self.statements(&function.body));
+        self.scopes.pop();
+        symbol
+    }
+    fn expr(&mut self, expr: &Node) {
+        match expr {
+            Node::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            },
+            Node::Parenthesis(inner) | Node::Unary(_, inner, _) => self.expr(inner),
+            Node::Litteral(_, _) | Node::This(_) | Node::Super(_, _) => {},
+            Node::Identifier(name, _, position) => self.reference(name, position),
+            Node::Assignment(name, value, _, position) => {
+                self.reference(name, position);
+                self.expr(value);
+            },
+            Node::Call { callee, arguments, .. } => {
+                self.expr(callee);
+                arguments.iter().for_each(|argument| self.expr(argument));
+            },
+            Node::Interpolation(parts, _) => parts.iter().for_each(|part| self.expr(part)),
+            Node::Get(object, _, _) => self.expr(object),
+            Node::Set(object, _, value, _) => {
+                self.expr(object);
+                self.expr(value);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Index;
+
+    #[test]
+    fn method_bodies_do_not_see_method_names() {
+        let source = "fun foo() {}\nclass A {\n  foo() { foo(); }\n}\n";
+        let index = Index::build(source);
+        // `foo()` called inside the method `foo`.
+        let declaration = index.find(&json!({ "line": 2, "character": 10 })).unwrap();
+        assert_eq!(index.file.line(declaration.position.start), 1);
+        // The method name itself still names the method.
+        let declaration = index.find(&json!({ "line": 2, "character": 2 })).unwrap();
+        assert_eq!(index.file.line(declaration.position.start), 3);
+    }
+}
@@ -1,29 +1,67 @@
 use std::fs;
 use std::path::PathBuf;
 
-use clap::{Parser, ValueEnum};
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
 
 use anyhow::anyhow;
-use interpreter::Interpreter;
-use parser::{AstFactory, Statement};
-use scanner::{Token, TokenType};
-mod interpreter;
-mod parser;
-mod scanner;
-mod position;
-mod environment;
+use codecrafters_interpreter::{
+    compile_file, compiler, dap,
+    debugger::{Debugger, Quit},
+    diagnostics::Diagnostic,
+    disassembler, formatter,
+    linter::{Linter, Rule},
+    lsp, optimizer,
+    parser::{AstFactory, Statement},
+    repl::Repl,
+    shell::Shell,
+    source_map::SourceMap,
+    scanner::{self, Token},
+    vm::Vm,
+    Interpreter, LoxError,
+};
 
 #[derive(Parser, Debug)]
 #[command(version, long_about = None)]
 struct Cli {
+    /// Defaults to `repl` when omitted
     #[arg(value_enum)]
-    command: Command,
+    command: Option<Command>,
 
     #[arg()]
-    file_path: PathBuf,
+    file_path: Option<PathBuf>,
 
     #[arg(short, long, default_value_t = false)]
-    debug: bool
+    debug: bool,
+
+    #[arg(short, long, value_enum, default_value_t = Backend::Tree)]
+    backend: Backend,
+
+    /// Fold constants and prune dead branches before running; `--debug`
+    /// then prints the optimized tree
+    #[arg(short = 'O', long, default_value_t = false)]
+    optimize: bool,
+
+    /// Line to pause at when running under `debug`; may be repeated
+    #[arg(long = "break", value_name = "LINE")]
+    breakpoints: Vec<usize>,
+
+    /// With `fmt`, exit non-zero instead of rewriting an unformatted file
+    #[arg(long, default_value_t = false)]
+    check: bool,
+
+    /// Lint rule to turn off under `lint`; may be repeated
+    #[arg(long = "allow", value_name = "RULE")]
+    allowed: Vec<Rule>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// Walk the syntax tree directly
+    #[clap(name = "tree")]
+    Tree,
+    /// Compile to bytecode and run it on the stack VM
+    #[clap(name = "vm")]
+    Vm,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -36,6 +74,26 @@ enum Command {
     Evaluate,
     #[clap(name = "run", alias = "r")]
     Run,
+    #[clap(name = "disassemble", alias = "d")]
+    Disassemble,
+    #[clap(name = "debug")]
+    Debug,
+    /// Rewrite a file in canonical style
+    #[clap(name = "fmt")]
+    Fmt,
+    /// Warn about suspicious code
+    #[clap(name = "lint")]
+    Lint,
+    /// Serve the Debug Adapter Protocol over stdio
+    #[clap(name = "dap")]
+    Dap,
+    /// Serve the Language Server Protocol over stdio
+    #[clap(name = "lsp")]
+    Lsp,
+    #[clap(name = "repl")]
+    Repl,
+    #[clap(name = "shell")]
+    Shell,
 }
 
 enum ExitCode {
@@ -52,7 +110,7 @@ impl From<i32> for ExitCode {
 }
 
 impl ExitCode {
-    fn exit(self) {
+    fn exit(self) -> ! {
         match self {
             ExitCode::Success => std::process::exit(0),
             ExitCode::Error(err) => std::process::exit(err),
@@ -60,17 +118,64 @@ impl ExitCode {
     }
 }
 
+/// Prints `error` to stderr, with a source snippet when it is a `Diagnostic`.
+fn report(error: &anyhow::Error, sources: &SourceMap) {
+    match error.downcast_ref::<Diagnostic>() {
+        Some(diagnostic) => eprint!("{}", sources.render_diagnostic(diagnostic)),
+        None => eprintln!("{}", error),
+    }
+}
+
+/// Prints every diagnostic in `error` and exits: 65 for static errors,
+/// 70 for runtime errors.
+fn fail(error: LoxError, sources: &SourceMap) -> ! {
+    eprint!("{}", sources.render(&error));
+    match error {
+        LoxError::ScanError(_) | LoxError::ParseError(_) => ExitCode::Error(65).exit(),
+        LoxError::Io(_) => ExitCode::Error(74).exit(),
+        LoxError::RuntimeError(_) | LoxError::Other(_) => ExitCode::Error(70).exit(),
+    }
+}
+
+/// Stack for the thread running commands. The tree-walking interpreter
+/// recurses on the host stack, and debug builds need this much to reach
+/// `MAX_CALL_DEPTH` before overflowing it.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn main() -> anyhow::Result<()> {
+    let runner = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(run)?;
+    match runner.join() {
+        Ok(result) => result,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+fn run() -> anyhow::Result<()> {
     let args = Cli::parse();
-    let file_contents: String = if let Ok(fc) = fs::read_to_string(&args.file_path) {
+    let command = args.command.unwrap_or(Command::Repl);
+    match command {
+        Command::Repl => return Ok(Repl::new().run(std::io::stdin().lock())?),
+        Command::Shell => return Shell::new().run(),
+        Command::Dap => return dap::serve(std::io::stdin().lock(), std::io::stdout()),
+        Command::Lsp => return lsp::serve(std::io::stdin().lock(), std::io::stdout()),
+        _ => {},
+    }
+    let Some(file_path) = args.file_path else {
+        Cli::command()
+            .error(ErrorKind::MissingRequiredArgument, "a file path is required for this command")
+            .exit();
+    };
+    let file_contents: String = if let Ok(fc) = fs::read_to_string(&file_path) {
         fc.to_owned()
     } else {
-        return Err(anyhow!("Failed to read file {}", args.file_path.display()));
+        return Err(anyhow!("Failed to read file {}", file_path.display()));
     };
+    let mut sources = SourceMap::new();
+    let file = sources.add(file_path.display().to_string(), file_contents.clone());
 
-    match args.command {
+    match command {
         Command::Tokenize => {
-            let tokens: Vec<Token> = scanner::scan(file_contents)?;
+            let tokens: Vec<Token> = scanner::scan_file(file, file_contents.clone());
             // You can use print statements as follows for debugging, they'll be visible when running tests.
             //
             let exit_code = if tokens.iter().any(|t| !t.is_valid()) {
@@ -80,10 +185,9 @@ fn main() -> anyhow::Result<()> {
             };
 
             for token in tokens {
-                if let TokenType::Invalid(e) = token.token_type {
-                    eprintln!("[line {}] Error: {}", token.position.line(), e);
-                } else {
-                    println!("{}", token);
+                match token.diagnostic() {
+                    Some(diagnostic) => eprint!("{}", sources.render_diagnostic(&diagnostic)),
+                    None => println!("{}", token),
                 }
             }
 
@@ -91,7 +195,7 @@ fn main() -> anyhow::Result<()> {
             exit_code.exit();
         }
         Command::Parse => {
-            let tokens: Vec<Token> = scanner::scan(file_contents)?;
+            let tokens: Vec<Token> = scanner::scan_file(file, file_contents.clone());
             // You can use print statements as follows for debugging, they'll be visible when running tests.
             //
             let mut exit_code = if tokens.iter().any(|t| !t.is_valid()) {
@@ -112,7 +216,7 @@ fn main() -> anyhow::Result<()> {
             match ast.parse_equality() {
                 Ok(h) => println!("{:?}", h),
                 Err(e) => {
-                    eprintln!("{}", e);
+                    report(&e, &sources);
                     exit_code = ExitCode::Error(65);
                 }
             };
@@ -120,7 +224,7 @@ fn main() -> anyhow::Result<()> {
             exit_code.exit();
         }
         Command::Evaluate => {
-            let tokens: Vec<Token> = scanner::scan(file_contents)?;
+            let tokens: Vec<Token> = scanner::scan_file(file, file_contents.clone());
             // You can use print statements as follows for debugging, they'll be visible when running tests.
             //
             let mut _exit_code = if tokens.iter().any(|t| !t.is_valid()) {
@@ -138,32 +242,23 @@ fn main() -> anyhow::Result<()> {
             }*/
 
             let mut ast = AstFactory::new(tokens);
-            let statement = ast.parse_equality()?;
-            let statement = Statement::Print(statement);
+            let statement = match ast.parse_equality() {
+                Ok(expr) => Statement::Print(expr),
+                Err(e) => {
+                    report(&e, &sources);
+                    ExitCode::Error(65).exit();
+                }
+            };
             let mut interpreter = Interpreter::new();
-            interpreter.execute(statement)?;
-            
+            if let Err(e) = interpreter.execute(statement) {
+                fail(e.into(), &sources);
+            }
         }
         Command::Run => {
-            let tokens: Vec<Token> = scanner::scan(file_contents)?;
-            // You can use print statements as follows for debugging, they'll be visible when running tests.
-            //
-            let mut _exit_code = if tokens.iter().any(|t| !t.is_valid()) {
-                ExitCode::Error(65)
-            } else {
-                ExitCode::Success
-            };
-
-            // for token in tokens.clone() {
-            //     if let TokenType::Invalid(e) = token.token_type {
-            //         eprintln!("[line {}] Error: {}", token.line, e);
-            //     } else {
-            //         println!("{}", token);
-            //     }
-            // }
-
-            let mut ast: AstFactory = AstFactory::new(tokens);
-            let statements = ast.parse_statements()?;
+            let mut statements = compile_file(file, &file_contents).unwrap_or_else(|e| fail(e, &sources));
+            if args.optimize {
+                statements = optimizer::optimize(statements);
+            }
             if args.debug {
                 println!("DEBUG: {{");
                 for stmt in statements.clone() {
@@ -171,8 +266,57 @@ fn main() -> anyhow::Result<()> {
                 }
                 println!("}}");
             }
+            let result = match args.backend {
+                Backend::Tree => Interpreter::new().interpret(statements),
+                Backend::Vm => Vm::new().interpret(&statements),
+            };
+            if let Err(e) = result {
+                fail(e, &sources);
+            }
+        }
+        Command::Debug => {
+            let statements = compile_file(file, &file_contents).unwrap_or_else(|e| fail(e, &sources));
             let mut interpreter = Interpreter::new();
-            interpreter.interpret(statements)?;
+            interpreter.set_hook(Box::new(Debugger::new(&file_contents, &args.breakpoints)));
+            match interpreter.interpret(statements) {
+                Ok(()) => {},
+                Err(LoxError::Other(e)) if e.is::<Quit>() => println!("{}", e),
+                Err(e) => fail(e, &sources),
+            }
+        }
+        Command::Fmt => {
+            let formatted = formatter::format(&file_contents).unwrap_or_else(|e| fail(e, &sources));
+            if formatted != file_contents {
+                if args.check {
+                    eprintln!("{} is not formatted", file_path.display());
+                    ExitCode::Error(1).exit();
+                }
+                fs::write(&file_path, formatted)?;
+            }
+        }
+        Command::Lint => {
+            let statements = compile_file(file, &file_contents).unwrap_or_else(|e| fail(e, &sources));
+            let mut linter = Linter::new();
+            for rule in args.allowed {
+                linter.disable(rule);
+            }
+            let warnings = linter.lint(&statements, &sources);
+            for warning in &warnings {
+                eprint!("{}", sources.render_diagnostic(warning));
+            }
+            if !warnings.is_empty() {
+                eprintln!("{} warning{} emitted", warnings.len(), if warnings.len() == 1 { "" } else { "s" });
+                ExitCode::Error(1).exit();
+            }
+        }
+        Command::Repl | Command::Shell | Command::Dap | Command::Lsp => unreachable!(),
+        Command::Disassemble => {
+            let mut statements = compile_file(file, &file_contents).unwrap_or_else(|e| fail(e, &sources));
+            if args.optimize {
+                statements = optimizer::optimize(statements);
+            }
+            let chunk = compiler::Compiler::compile(&statements);
+            print!("{}", disassembler::disassemble(&chunk, "<script>", sources.get(file).unwrap()));
         }
     }
     Ok(())
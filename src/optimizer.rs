@@ -0,0 +1,208 @@
+use crate::parser::{BinaryOperator, Function, Litteral, Node, Statement};
+
+/// Rewrites resolved statements into cheaper equivalents: folds constant
+/// sub-expressions, drops grouping parentheses and removes `if` branches
+/// whose condition is a literal.
+pub fn optimize(statements: Vec<Statement>) -> Vec<Statement> {
+    statements.into_iter()
+        .map(optimize_statement)
+        .filter(|statement| !matches!(statement, Statement::Block(statements) if statements.is_empty()))
+        .collect()
+}
+
+fn optimize_statement(statement: Statement) -> Statement {
+    match statement {
+        Statement::Expression(expr) => Statement::Expression(optimize_expr(expr)),
+        Statement::Print(expr) => Statement::Print(optimize_expr(expr)),
+        Statement::Return(expr, position) => Statement::Return(expr.map(optimize_expr), position),
+        Statement::VarDecl(name, initializer, position) => {
+            Statement::VarDecl(name, optimize_expr(initializer), position)
+        },
+        Statement::Block(statements) => Statement::Block(optimize(statements)),
+        Statement::If(condition, then, els) => match optimize_expr(condition) {
+            // `if` does not open a scope, so the taken branch can stand in for it.
+            Node::Litteral(value, _) if value.is_truthy() => optimize_statement(*then),
+            Node::Litteral(_, _) => match els {
+                Some(els) => optimize_statement(*els),
+                None => Statement::Block(Vec::new()),
+            },
+            condition => Statement::If(
+                condition,
+                Box::new(optimize_statement(*then)),
+                els.map(|els| Box::new(optimize_statement(*els)))
+            ),
+        },
+        Statement::While(condition, body) => {
+            Statement::While(optimize_expr(condition), Box::new(optimize_statement(*body)))
+        },
+        Statement::For(init, condition, increment, body) => Statement::For(
+            init.map(|init| Box::new(optimize_statement(*init))),
+            condition.map(optimize_expr),
+            increment.map(optimize_expr),
+            Box::new(optimize_statement(*body))
+        ),
+        Statement::Function(function) => Statement::Function(optimize_function(function)),
+        Statement::Class(name, superclass, methods, position) => Statement::Class(
+            name,
+            superclass,
+            methods.into_iter().map(optimize_function).collect(),
+            position
+        ),
+    }
+}
+
+fn optimize_function(function: Function) -> Function {
+    Function {
+        body: optimize(function.body),
+        ..function
+    }
+}
+
+/// Folds `expr` bottom-up. Operations that would fail at runtime are left in
+/// place so the error is still reported when they run.
+pub fn optimize_expr(expr: Node) -> Node {
+    match expr {
+        Node::Parenthesis(inner) => optimize_expr(*inner),
+        Node::Unary(operator, operand, position) => {
+            let operand = optimize_expr(*operand);
+            if let Node::Litteral(value, _) = &operand {
+                if let Ok(value) = operator.eval(value.clone()) {
+                    return Node::Litteral(value, position);
+                }
+            }
+            Node::Unary(operator, Box::new(operand), position)
+        },
+        Node::Binary { left, right, operator, position } => {
+            let left = optimize_expr(*left);
+            let right = optimize_expr(*right);
+            if let (Node::Litteral(l, _), Node::Litteral(r, _)) = (&left, &right) {
+                // Same short-circuiting as the interpreter.
+                let folded = match operator {
+                    _ if operator.short_circuits(l) => Ok(l.clone()),
+                    BinaryOperator::Or | BinaryOperator::And => Ok(r.clone()),
+                    _ => operator.eval(l.clone(), r.clone()),
+                };
+                if let Ok(value) = folded {
+                    return Node::Litteral(value, position);
+                }
+            }
+            Node::Binary {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator,
+                position,
+            }
+        },
+        Node::Assignment(name, value, depth, position) => {
+            Node::Assignment(name, Box::new(optimize_expr(*value)), depth, position)
+        },
+        Node::Call { callee, arguments, position } => Node::Call {
+            callee: Box::new(optimize_expr(*callee)),
+            arguments: arguments.into_iter().map(optimize_expr).collect(),
+            position,
+        },
+        Node::Get(object, name, position) => Node::Get(Box::new(optimize_expr(*object)), name, position),
+        Node::Set(object, name, value, position) => {
+            Node::Set(Box::new(optimize_expr(*object)), name, Box::new(optimize_expr(*value)), position)
+        },
+        Node::Interpolation(parts, position) => {
+            let parts: Vec<Node> = parts.into_iter().map(optimize_expr).collect();
+            let mut folded = String::new();
+            for part in &parts {
+                match part {
+                    Node::Litteral(value, _) => folded.push_str(&value.to_string()),
+                    _ => return Node::Interpolation(parts, position),
+                }
+            }
+            Node::Litteral(Litteral::String(folded), position)
+        },
+        expr @ (Node::Litteral(_, _) | Node::Identifier(_, _, _) | Node::This(_) | Node::Super(_, _)) => expr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{optimize, optimize_expr};
+    use crate::{
+        compile, compile_file, interpreter::Interpreter, lox::Capture, parser::{Node, Statement},
+        source_map::SourceMap,
+    };
+
+    /// Optimizes the expression statement `source` and returns its value if
+    /// it folded to a literal.
+    fn fold(source: &str) -> Option<String> {
+        let mut statements = compile(&format!("{};", source)).unwrap();
+        let Some(Statement::Expression(expr)) = statements.pop() else {
+            panic!("not an expression: {}", source);
+        };
+        match optimize_expr(expr) {
+            Node::Litteral(value, _) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Printed output of `source` followed by its rendered error, if any.
+    fn run(source: &str, optimized: bool) -> String {
+        let mut sources = SourceMap::new();
+        let mut statements = compile_file(sources.add("", source), source).unwrap();
+        if optimized {
+            statements = optimize(statements);
+        }
+        let capture = Capture::default();
+        let result = Interpreter::with_output(Box::new(capture.clone())).interpret(statements);
+        capture.take() + &result.err().map(|e| sources.render(&e)).unwrap_or_default()
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_eq!(fold("1 + 2").as_deref(), Some("3"));
+        assert_eq!(fold("2 * (3 - 1)").as_deref(), Some("4"));
+        assert_eq!(fold("\"con\" + \"cat\"").as_deref(), Some("concat"));
+        assert_eq!(fold("!true").as_deref(), Some("false"));
+        assert_eq!(fold("-(1 + 1)").as_deref(), Some("-2"));
+        assert_eq!(fold("1 < 2 and \"yes\"").as_deref(), Some("yes"));
+        assert_eq!(fold("nil or nil").as_deref(), Some("nil"));
+        assert_eq!(fold("nil and 1").as_deref(), Some("nil"));
+        assert_eq!(fold("nil == nil").as_deref(), Some("true"));
+        assert_eq!(fold("\"${1 + 1} apples\"").as_deref(), Some("2 apples"));
+    }
+
+    #[test]
+    fn leaves_failing_operations() {
+        assert_eq!(fold("\"a\" - 1"), None);
+        assert_eq!(fold("-\"a\""), None);
+        assert_eq!(fold("nil < 1"), None);
+        assert_eq!(fold("1 + x"), None);
+    }
+
+    #[test]
+    fn removes_dead_branches() {
+        let statements = optimize(compile("if (false) print 1;").unwrap());
+        assert!(statements.is_empty());
+        let statements = optimize(compile("if (false) print 1; else print 2;").unwrap());
+        assert!(matches!(statements[..], [Statement::Print(Node::Litteral(_, _))]));
+        let statements = optimize(compile("if (1 + 1 == 2) print 1; else print 2;").unwrap());
+        assert!(matches!(&statements[..], [Statement::Print(Node::Litteral(value, _))] if value.to_string() == "1"));
+    }
+
+    #[test]
+    fn behaves_like_unoptimized() {
+        let programs = [
+            "print 1 / 0;",
+            "print \"a\" - 1;",
+            "print -\"a\";",
+            "print 1 + 2 * 3;",
+            "if (false) print undefined; print \"ok\";",
+            "
+            fun f(label) { print label; return true; }
+            print false and f(\"and\");
+            print true or f(\"or\");
+            print true and f(\"taken\");
+            print nil or f(\"also taken\");
+            ",
+        ];
+        for source in programs {
+            assert_eq!(run(source, true), run(source, false), "optimizing changed:\n{}", source);
+        }
+    }
+}
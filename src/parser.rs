@@ -1,6 +1,6 @@
-use std::{collections::VecDeque, fmt::Display};
+use std::{cell::RefCell, collections::VecDeque, fmt::Display, rc::Rc};
 
-use crate::{position::Position, scanner::{Token, TokenType}};
+use crate::{diagnostics::{self, Diagnostic}, interpreter::{Class, Closure, Instance, NativeFunction}, position::Position, scanner::{Token, TokenType}};
 use anyhow::anyhow;
 
 #[derive(Clone)]
@@ -14,27 +14,59 @@ pub enum Node {
     Parenthesis(Box<Node>),
     Unary(UnaryOperator, Box<Node>, Position),
     Litteral(Litteral, Position),
-    Identifier(String, Position),
-    Assignment(String, Box<Node>, Position),
+    Identifier(String, Option<usize>, Position),
+    Assignment(String, Box<Node>, Option<usize>, Position),
+    Call {
+        callee: Box<Node>,
+        arguments: Vec<Node>,
+        position: Position,
+    },
+    Get(Box<Node>, String, Position),
+    Set(Box<Node>, String, Box<Node>, Position),
+    This(Position),
+    Super(String, Position),
+    /// An interpolated string: its literal segments and the embedded
+    /// expressions, in order.
+    Interpolation(Vec<Node>, Position),
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub enum Litteral {
     Number(f64),
     Boolean(bool),
+    #[default]
     Nil,
     String(String),
+    Function(Rc<Closure>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    /// Position of each parameter name, parallel to `params`.
+    pub param_positions: Vec<Position>,
+    pub body: Vec<Statement>,
+    pub position: Position,
 }
 
 #[derive(Clone, Debug)]
 pub enum Statement {
     Expression(Node),
     Print(Node),
-    VarDecl(String, Node),
+    /// Name, initializer and the position of the name.
+    VarDecl(String, Node, Position),
     Block(Vec<Statement>),
     If(Node, Box<Statement>, Option<Box<Statement>>),
     While(Node, Box<Statement>),
     For(Option<Box<Statement>>, Option<Node>, Option<Node>, Box<Statement>),
+    Function(Function),
+    /// The returned value, if any, and the position of `return`.
+    Return(Option<Node>, Position),
+    Class(String, Option<Node>, Vec<Function>, Position),
 }
 
 impl Display for Statement {
@@ -42,7 +74,7 @@ impl Display for Statement {
         match self {
             Statement::Print(t) => write!(f, "print: {}", t)?,
             Statement::Expression(e) => write!(f, "expr: {}", e)?,
-            Statement::VarDecl(i, e) => write!(f, "decl: {} = {}", i, e)?,
+            Statement::VarDecl(i, e, _) => write!(f, "decl: {} = {}", i, e)?,
             Statement::Block(block) => {
                 writeln!(f, "block: {{\n")?;
                 for stmnt in block {
@@ -64,7 +96,26 @@ impl Display for Statement {
             Statement::For(init, con, inc, body) => {
                 writeln!(f, "for {:?}, {:?}, {:?}", init, con, inc)?;
                 writeln!(f, "do {}", body)?;
-            }
+            },
+            Statement::Function(function) => {
+                writeln!(f, "fun {}({}) {{", function.name, function.params.join(", "))?;
+                for stmnt in &function.body {
+                    writeln!(f, "\t{}", stmnt)?;
+                }
+                writeln!(f, "}}")?;
+            },
+            Statement::Return(Some(e), _) => write!(f, "return: {}", e)?,
+            Statement::Return(None, _) => write!(f, "return: nil")?,
+            Statement::Class(name, superclass, methods, _) => {
+                match superclass {
+                    Some(superclass) => writeln!(f, "class {} < {} {{", name, superclass)?,
+                    None => writeln!(f, "class {} {{", name)?,
+                }
+                for method in methods {
+                    writeln!(f, "\t{}({})", method.name, method.params.join(", "))?;
+                }
+                writeln!(f, "}}")?;
+            },
         }
         Ok(())
     }
@@ -77,6 +128,10 @@ impl Display for Litteral {
             Litteral::Boolean(b) => write!(f, "{}", b),
             Litteral::Nil => write!(f, "nil"),
             Litteral::String(s) => write!(f, "{}", s),
+            Litteral::Function(fun) => write!(f, "<fn {}>", fun.declaration.name),
+            Litteral::Native(_) => write!(f, "<native fn>"),
+            Litteral::Class(class) => write!(f, "{}", class.name),
+            Litteral::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
         }
     }
 }
@@ -88,11 +143,15 @@ impl std::fmt::Debug for Litteral {
             Litteral::Boolean(b) => write!(f, "{}", b),
             Litteral::Nil => write!(f, "nil"),
             Litteral::String(s) => write!(f, "{}", s),
+            Litteral::Function(fun) => write!(f, "<fn {}>", fun.declaration.name),
+            Litteral::Native(native) => write!(f, "<native fn {}>", native.name),
+            Litteral::Class(class) => write!(f, "{}", class.name),
+            Litteral::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum UnaryOperator {
     Not,
     Neg,
@@ -107,7 +166,7 @@ impl Display for UnaryOperator {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum BinaryOperator {
     Add,
     Sub,
@@ -152,6 +211,7 @@ pub struct AstFactory {
     //head: Node,
     current: usize,
     tokens: VecDeque<Token>,
+    errors: Vec<Diagnostic>,
 }
 
 impl AstFactory {
@@ -159,24 +219,91 @@ impl AstFactory {
         Self {
             current: 0,
             tokens: input.into(),
+            errors: Vec::new(),
         }
     }
     pub fn is(&self, token_type: TokenType) -> bool {
         self.tokens[self.current].token_type == token_type
     }
-    pub fn parse_statements(&mut self) -> anyhow::Result<Vec<Statement>> {
+    fn check(&self, token_type: TokenType) -> bool {
+        self.tokens.get(self.current).is_some_and(|t| t.token_type == token_type)
+    }
+    fn consume(&mut self, token_type: TokenType, message: &str) -> anyhow::Result<Token> {
+        if self.check(token_type) {
+            self.current += 1;
+            return Ok(self.tokens[self.current - 1].clone());
+        }
+        Err(self.error_at_current(message))
+    }
+    fn consume_identifier(&mut self, message: &str) -> anyhow::Result<Token> {
+        match self.tokens.get(self.current) {
+            Some(token @ Token { token_type: TokenType::Identifier(_), .. }) => {
+                let token = token.clone();
+                self.current += 1;
+                Ok(token)
+            },
+            _ => Err(self.error_at_current(message)),
+        }
+    }
+    fn error_at_current(&self, message: &str) -> anyhow::Error {
+        self.error_with_code(diagnostics::EXPECTED_TOKEN, message)
+    }
+    fn error_with_code(&self, code: &'static str, message: &str) -> anyhow::Error {
+        self.diagnostic_at_current(code, message).into()
+    }
+    fn diagnostic_at_current(&self, code: &'static str, message: &str) -> Diagnostic {
+        match self.tokens.get(self.current) {
+            Some(token) => Diagnostic::error(code, message, token.position.clone())
+                .with_note(format!("found '{}'", token.raw)),
+            None => {
+                let position = self.tokens.back()
+                    .map_or(Position::default(), |t| t.position.clone());
+                Diagnostic::error(code, message, position)
+                    .with_note("found end of input")
+            },
+        }
+    }
+    /// Records `error` from the statement starting at `start` and skips ahead
+    /// to the next one: past a `;`, or up to a statement keyword or a `}`.
+    fn synchronize(&mut self, error: anyhow::Error, start: usize) {
+        let diagnostic = match error.downcast::<Diagnostic>() {
+            Ok(diagnostic) => diagnostic,
+            Err(error) => self.diagnostic_at_current(diagnostics::EXPECTED_TOKEN, &error.to_string()),
+        };
+        self.errors.push(diagnostic);
+        if self.current == start {
+            self.current += 1;
+        }
+        while self.current < self.tokens.len() {
+            if self.tokens[self.current - 1].token_type == TokenType::SemiColon {
+                return;
+            }
+            match self.tokens[self.current].token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::RightBrace => return,
+                _ => self.current += 1,
+            }
+        }
+    }
+    /// Parses every statement in the input, recovering after syntax errors.
+    /// Returns the statements that parsed along with every error found.
+    pub fn parse_statements(&mut self) -> (Vec<Statement>, Vec<Diagnostic>) {
         let mut out: Vec<Statement> = Vec::new();
         while self.current < self.tokens.len() {
-            let node = match self.parse_statement() {
-                Ok(expr) => expr,
-                Err(e) => {
-                    eprintln!("{}", e);
-                    std::process::exit(65);
-                }
-            };
-            out.push(node);
+            let start = self.current;
+            match self.parse_statement() {
+                Ok(statement) => out.push(statement),
+                Err(e) => self.synchronize(e, start),
+            }
         }
-        Ok(out)
+        (out, std::mem::take(&mut self.errors))
     }
     pub fn parse_statement(&mut self) -> anyhow::Result<Statement> {
         let out = match self.tokens[self.current].token_type {
@@ -187,27 +314,19 @@ impl AstFactory {
             },
             TokenType::Var => {
                 self.current += 1;
-                let identifier = self.parse_number()?;
-                if let Node::Identifier(name, _) = identifier {
-                    match self.tokens[self.current].token_type {
-                        TokenType::SemiColon => {
-                            let pos = self.tokens[self.current].position.clone();
-                            let expr = Node::Litteral(Litteral::Nil, pos);
-                            self.current += 1;
-                            Ok(Statement::VarDecl(name, expr))
-                        },
-                        TokenType::Equal => {
-                            self.current += 1;
-                            let expr = self.parse_assignment()?;
-                            Ok(Statement::VarDecl(name, expr)) 
-                        },
-                        _ => {
-                            eprintln!("Expected = or ; after variable declearation!");
-                            std::process::exit(70);
-                        }
-                    }
+                let name = self.consume_identifier("Expect variable name.")?;
+                let (name, position) = (name.raw, name.position);
+                if self.check(TokenType::SemiColon) {
+                    let pos = self.tokens[self.current].position.clone();
+                    let expr = Node::Litteral(Litteral::Nil, pos);
+                    self.current += 1;
+                    Ok(Statement::VarDecl(name, expr, position))
+                } else if self.check(TokenType::Equal) {
+                    self.current += 1;
+                    let expr = self.parse_assignment()?;
+                    Ok(Statement::VarDecl(name, expr, position))
                 } else {
-                    Err(anyhow!("Expected identifier got {}", identifier))
+                    Err(self.error_at_current("Expect '=' or ';' after variable name."))
                 }
             }
             TokenType::LeftBrace => {
@@ -220,28 +339,25 @@ impl AstFactory {
                             break;
                         },
                         TokenType::SemiColon => self.current += 1,
-                        _ => statements.push(self.parse_statement()?)
+                        _ => {
+                            let start = self.current;
+                            match self.parse_statement() {
+                                Ok(statement) => statements.push(statement),
+                                Err(e) => self.synchronize(e, start),
+                            }
+                        },
                     }
                     if self.current == self.tokens.len() {
-                        eprintln!("[line {}] Error at end: Expect '}}'.", 
-                            self.tokens[self.current - 1].position.line());
-                        std::process::exit(65);
+                        return Err(self.error_at_current("Expect '}'."));
                     }
                 }
                 Ok(Statement::Block(statements))
             },
             TokenType::If => {
                 self.current += 1;
-                match self.tokens[self.current].token_type {
-                    TokenType::LeftParen => {},
-                    _ => {
-                        eprintln!("Expected ( after if!");
-                        std::process::exit(65);
-                    }
-                }
-                self.current += 1;
+                self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
                 let condition = self.parse_assignment()?;
-                self.current += 1;
+                self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
                 let statement = Box::new(self.parse_statement()?); 
                 let else_stmnt = if self.current < self.tokens.len() {
                     match self.tokens[self.current].token_type {
@@ -249,9 +365,7 @@ impl AstFactory {
                             self.current += 1;
                             Some(Box::new(self.parse_statement()?))
                         },
-                        ref t => {
-                            None
-                        }
+                        _ => None
                     }
                 } else { None };
 
@@ -259,130 +373,128 @@ impl AstFactory {
             },
             TokenType::While => {
                 self.current += 1;
-                match self.tokens[self.current].token_type {
-                    TokenType::LeftParen => {},
-                    _ => {
-                        eprintln!("Expected ( after if!");
-                        std::process::exit(65);
-                    }
-                }
-                self.current += 1;
+                self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
                 let condition = self.parse_assignment()?;
-                self.current += 1;
+                self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
                 let statement = Box::new(self.parse_statement()?); 
 
                 Ok(Statement::While(condition, statement))
             }
             TokenType::For => {
                 self.current += 1;
-                match self.tokens[self.current].token_type {
-                    TokenType::LeftParen => {},
-                    _ => {
-                        eprintln!("Expected ( after for!");
-                        std::process::exit(65);
-                    }
-                }
-                self.current += 1;
-                if self.is(TokenType::SemiColon) {
+                self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+                let initializer = if self.check(TokenType::SemiColon) {
                     self.current += 1;
-                    if self.is(TokenType::SemiColon) {
-                        self.current += 1;
-                        if self.is(TokenType::RightParen) {
-                            self.current += 1;
-                            let body = Box::new(self.parse_statement()?);
-                            return Ok(Statement::For(None, None, None, body))
-                        }
-                        let increment = self.parse_assignment()?;
-                        self.current += 1;
-                        let body = Box::new(self.parse_statement()?);
-                        return Ok(Statement::For(None, None, Some(increment), body))
-                    } else {
-                        let condition = self.parse_assignment()?;
-                        self.current += 1;
-                        if self.is(TokenType::RightParen) {
-                            self.current += 1;
-                            let body = Box::new(self.parse_statement()?);
-                            return Ok(Statement::For(None, Some(condition), None, body))
-                        }
-                    }
+                    None
                 } else {
+                    Some(Box::new(self.parse_statement()?))
+                };
+                let condition = if self.check(TokenType::SemiColon) {
+                    None
+                } else {
+                    Some(self.parse_assignment()?)
+                };
+                self.consume(TokenType::SemiColon, "Expect ';' after loop condition.")?;
+                let increment = if self.check(TokenType::RightParen) {
+                    None
+                } else {
+                    Some(self.parse_assignment()?)
+                };
+                self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+                let body = Box::new(self.parse_statement()?);
 
-                }
-                if let Ok(constructor) = self.parse_statement() {
-                    if let Ok(condition) = self.parse_assignment() {
-                        self.current += 1;
-                        if let Ok(incrementer) = self.parse_assignment() { 
-                            self.current += 1;
-                            let body = Box::new(self.parse_statement()?);
-                            return Ok(
-                                Statement::For(
-                                    Some(Box::new(constructor)), Some(condition), Some(incrementer), body
-                                )
-                            );
-                        }
-                        self.current += 1;
-                        let body = Box::new(self.parse_statement()?);
-                        return Ok(
-                            Statement::For(
-                                Some(Box::new(constructor)), Some(condition), None, body
-                            )
-                        );
-                    }
+                Ok(Statement::For(initializer, condition, increment, body))
+            },
+            TokenType::Fun => {
+                self.current += 1;
+                Ok(Statement::Function(self.parse_function()?))
+            },
+            TokenType::Class => {
+                self.current += 1;
+                let name = self.consume_identifier("Expect class name.")?;
+                let (name, position) = (name.raw, name.position);
+                let superclass = if self.check(TokenType::Less) {
                     self.current += 1;
-                    let body = Box::new(self.parse_statement()?);
-                    return Ok(
-                        Statement::For(
-                            Some(Box::new(constructor)), None, None, body
-                        )
-                    );
+                    let token = self.consume_identifier("Expect superclass name.")?;
+                    Some(Node::Identifier(token.raw, None, token.position))
+                } else {
+                    None
+                };
+                self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+                let mut methods: Vec<Function> = Vec::new();
+                while self.current < self.tokens.len() && !self.is(TokenType::RightBrace) {
+                    methods.push(self.parse_function()?);
                 }
+                self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+                Ok(Statement::Class(name, superclass, methods, position))
+            },
+            TokenType::Return => {
+                let position = self.tokens[self.current].position.clone();
                 self.current += 1;
-                println!("body");
-                let body = Box::new(self.parse_statement()?);
-
-                Ok(Statement::For(
-                    None, None, None, body)
-                )
-            }
+                let value = match self.tokens.get(self.current) {
+                    None | Some(Token { token_type: TokenType::SemiColon, .. }) => None,
+                    _ => Some(self.parse_assignment()?)
+                };
+                Ok(Statement::Return(value, position))
+            },
             _ => {
                 let value = self.parse_assignment()?;
                 Ok(Statement::Expression(value))
             }
         };
 
-        match self.tokens.get(self.current) {
-            Some(Token {
-                position: _,
-                raw: _,
-                token_type: TokenType::SemiColon
-            })=> {
-                self.current += 1;
-            }
-            _ => (),
+        if self.check(TokenType::SemiColon) {
+            self.current += 1;
         }
         
         out
     }
+    fn parse_function(&mut self) -> anyhow::Result<Function> {
+        let name = self.consume_identifier("Expect function name.")?;
+        let (name, position) = (name.raw, name.position);
+        self.consume(TokenType::LeftParen, "Expect '(' after function name.")?;
+        let mut params: Vec<String> = Vec::new();
+        let mut param_positions: Vec<Position> = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                let param = self.consume_identifier("Expect parameter name.")?;
+                params.push(param.raw);
+                param_positions.push(param.position);
+                if self.check(TokenType::Comma) {
+                    self.current += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        if !self.check(TokenType::LeftBrace) {
+            return Err(self.error_at_current("Expect '{' before function body."));
+        }
+        let body = match self.parse_statement()? {
+            Statement::Block(body) => body,
+            _ => unreachable!(),
+        };
+        Ok(Function { name, params, param_positions, body, position })
+    }
     fn parse_assignment(&mut self) -> anyhow::Result<Node> {
         let identifier: Node = self.parse_or()?;
-        while self.current < self.tokens.len() {
-            match self.tokens[self.current].token_type {
-                TokenType::Equal => {
-                    if let Node::Identifier(name, pos) = identifier {
-                        self.current += 1;
-                        let value = self.parse_assignment()?;
-                        let position = Position::range(
-                            pos,
-                            value.position()
-                        );
-                        let node = Node::Assignment(name, Box::new(value), position); 
-                        return Ok(node);
-                    }
-                },
-                _ => break
-            }
+        if !self.check(TokenType::Equal) {
+            return Ok(identifier);
+        }
+        let equals = self.tokens[self.current].clone();
+        self.current += 1;
+        let value = self.parse_assignment()?;
+        let position = Position::range(identifier.position(), value.position());
+        match identifier {
+            Node::Identifier(name, _, _) => Ok(Node::Assignment(name, Box::new(value), None, position)),
+            Node::Get(object, name, _) => Ok(Node::Set(object, name, Box::new(value), position)),
+            _ => Err(Diagnostic::error(
+                diagnostics::INVALID_ASSIGNMENT,
+                "Invalid assignment target.",
+                equals.position
+            ).with_help("only variables and fields can be assigned to").into()),
         }
-        Ok(identifier)
     }
 
     pub fn parse_or(&mut self) -> anyhow::Result<Node> {
@@ -392,9 +504,6 @@ impl AstFactory {
                 TokenType::Or => {
                     let op = self.tokens[self.current].clone();
                     self.current += 1;
-                    if self.current >= self.tokens.len() {
-                        break;
-                    }
                     let right = Box::new(self.parse_and()?);
                     let position = Position::range(node.position(), right.position());
                     node = Node::Binary {
@@ -417,9 +526,6 @@ impl AstFactory {
                 TokenType::And => {
                     let op = self.tokens[self.current].clone();
                     self.current += 1;
-                    if self.current >= self.tokens.len() {
-                        break;
-                    }
                     let right = Box::new(self.parse_equality()?);
                     let position = Position::range(node.position(), right.position());
                     node = Node::Binary {
@@ -446,9 +552,6 @@ impl AstFactory {
                 | TokenType::Less => {
                     let op = self.tokens[self.current].clone();
                     self.current += 1;
-                    if self.current >= self.tokens.len() {
-                        break;
-                    }
                     let right = Box::new(self.parse_term()?);
                     let position = Position::range(node.position(), right.position());
                     node = Node::Binary {
@@ -471,9 +574,6 @@ impl AstFactory {
                 TokenType::Plus | TokenType::Minus => {
                     let op = self.tokens[self.current].clone();
                     self.current += 1;
-                    if self.current >= self.tokens.len() {
-                        break;
-                    }
                     let right = Box::new(self.parse_factor()?);
                     let position = Position::range(node.position(), right.position());
                     node = Node::Binary {
@@ -496,9 +596,6 @@ impl AstFactory {
                 TokenType::Star | TokenType::Slash => {
                     let op = self.tokens[self.current].clone();
                     self.current += 1;
-                    if self.current >= self.tokens.len() {
-                        break;
-                    }
                     let right = Box::new(self.parse_exponent()?);
                     let position = Position::range(node.position(), right.position());
                     node = Node::Binary {
@@ -521,9 +618,6 @@ impl AstFactory {
                 TokenType::Carrot => {
                     let op = self.tokens[self.current].clone();
                     self.current += 1;
-                    if self.current >= self.tokens.len() {
-                        break;
-                    }
                     let right = Box::new(self.parse_primary()?);
                     let position = Position::range(node.position(), right.position());
                     node = Node::Binary {
@@ -541,15 +635,53 @@ impl AstFactory {
 
     fn parse_primary(&mut self) -> anyhow::Result<Node> {
         if self.current >= self.tokens.len() {
-            return Err(anyhow!("Out of bounds access in parse_primary"));
+            return Err(self.error_with_code(diagnostics::EXPECTED_EXPRESSION, "Expect expression."));
         }
         match self.tokens[self.current].token_type.clone() {
-            TokenType::LeftParen => self.parse_paren(),
             TokenType::Bang | TokenType::Minus => self.parse_unary(),
-            _ => self.parse_number(),
+            _ => self.parse_call(),
         }
     }
 
+    fn parse_call(&mut self) -> anyhow::Result<Node> {
+        let mut node: Node = self.parse_paren()?;
+        loop {
+            if self.check(TokenType::LeftParen) {
+                node = self.finish_call(node)?;
+            } else if self.check(TokenType::Dot) {
+                self.current += 1;
+                let name = self.consume_identifier("Expect property name after '.'.")?;
+                let position = Position::range(node.position(), name.position);
+                node = Node::Get(Box::new(node), name.raw, position);
+            } else {
+                break;
+            }
+        }
+        Ok(node)
+    }
+
+    fn finish_call(&mut self, callee: Node) -> anyhow::Result<Node> {
+        self.current += 1;
+        let mut arguments: Vec<Node> = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                arguments.push(self.parse_assignment()?);
+                if self.check(TokenType::Comma) {
+                    self.current += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+        let position = Position::range(callee.position(), paren.position);
+        Ok(Node::Call {
+            callee: Box::new(callee),
+            arguments,
+            position,
+        })
+    }
+
     fn parse_unary(&mut self) -> anyhow::Result<Node> {
         let op = self.tokens[self.current].clone();
         self.current += 1;
@@ -560,47 +692,25 @@ impl AstFactory {
     }
 
     fn parse_paren(&mut self) -> anyhow::Result<Node> {
-        let mut open_p = 0;
-        let mut private_tokens: VecDeque<Token> = VecDeque::new();
-
-        match self.tokens[self.current].token_type {
-            TokenType::LeftParen => {}
-            _ => {
-                return self.parse_number();
-            }
-        };
-        self.current += 1;
-        open_p += 1;
-        while self.current < self.tokens.len() && open_p != 0 {
-            match self.tokens[self.current].token_type.clone() {
-                TokenType::LeftParen => open_p += 1,
-                TokenType::RightParen => open_p -= 1,
-                _x => (), //println!("{:?}", x)
-            }
-            private_tokens.push_back(self.tokens[self.current].clone());
-            self.current += 1;
-        }
-        if open_p != 0 {
-            return Err(anyhow!("Unescaped parenthesis"));
+        if !self.check(TokenType::LeftParen) {
+            return self.parse_number();
         }
-        let mut parser = AstFactory {
-            tokens: private_tokens,
-            current: 0,
-        };
-        let node = parser.parse_assignment()?;
+        self.current += 1;
+        let node = self.parse_assignment()?;
+        self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
         Ok(Node::Parenthesis(Box::new(node)))
     }
 
     fn parse_number(&mut self) -> anyhow::Result<Node> {
         if self.current >= self.tokens.len() {
-            return Err(anyhow!("Out of bounds access in parse_number"));
+            return Err(self.error_with_code(diagnostics::EXPECTED_EXPRESSION, "Expect expression."));
         }
         let position = self.tokens[self.current].position.clone();
         match &self.tokens[self.current].token_type {
             TokenType::Number(x) => {
                 let number = *x;
                 self.current += 1;
-                Ok(Node::Litteral(Litteral::Number(number as f64), position))
+                Ok(Node::Litteral(Litteral::Number(number), position))
             }
             TokenType::True => {
                 self.current += 1;
@@ -618,15 +728,44 @@ impl AstFactory {
                 self.current += 1;
                 Ok(Node::Litteral(Litteral::String(s.clone()), position))
             },
+            TokenType::Interpolation(s) => {
+                let mut parts = vec![Node::Litteral(Litteral::String(s.clone()), position.clone())];
+                self.current += 1;
+                loop {
+                    parts.push(self.parse_assignment()?);
+                    let Some(segment) = self.tokens.get(self.current).cloned() else {
+                        return Err(self.error_at_current("Expect '}' after interpolated expression."));
+                    };
+                    match segment.token_type {
+                        TokenType::Interpolation(s) => {
+                            parts.push(Node::Litteral(Litteral::String(s), segment.position));
+                            self.current += 1;
+                        },
+                        TokenType::StringLitteral(s) => {
+                            let end = segment.position.clone();
+                            parts.push(Node::Litteral(Litteral::String(s), segment.position));
+                            self.current += 1;
+                            return Ok(Node::Interpolation(parts, Position::range(position, end)));
+                        },
+                        _ => return Err(self.error_at_current("Expect '}' after interpolated expression.")),
+                    }
+                }
+            },
             TokenType::Identifier(i) => {
                 self.current += 1;
-                Ok(Node::Identifier(i.clone(), position)) 
+                Ok(Node::Identifier(i.clone(), None, position)) 
+            },
+            TokenType::This => {
+                self.current += 1;
+                Ok(Node::This(position))
+            },
+            TokenType::Super => {
+                self.current += 1;
+                self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+                let method = self.consume_identifier("Expect superclass method name.")?;
+                Ok(Node::Super(method.raw, Position::range(position, method.position)))
             },
-            _ => Err(anyhow!(
-                "[line {}] Error at '{}': Expect expression.",
-                &self.tokens[self.current].position.line(),
-                &self.tokens[self.current].raw
-            )),
+            _ => Err(self.error_with_code(diagnostics::EXPECTED_EXPRESSION, "Expect expression.")),
         }
     }
 }
@@ -677,8 +816,26 @@ impl Display for Node {
                 position,
             } => write!(f, "({} {} {})", operator, left, right),
             Node::Parenthesis(e) => write!(f, "(group {})", e),
-            Node::Identifier(i, _) => write!(f, "_{}", i),
-            Node::Assignment(i, v, _) => write!(f, "{} = {}", i, v)
+            Node::Identifier(i, _, _) => write!(f, "_{}", i),
+            Node::Assignment(i, v, _, _) => write!(f, "{} = {}", i, v),
+            Node::Call { callee, arguments, .. } => {
+                write!(f, "(call {}", callee)?;
+                for arg in arguments {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            },
+            Node::Get(object, name, _) => write!(f, "{}.{}", object, name),
+            Node::Set(object, name, value, _) => write!(f, "{}.{} = {}", object, name, value),
+            Node::This(_) => write!(f, "this"),
+            Node::Super(method, _) => write!(f, "super.{}", method),
+            Node::Interpolation(parts, _) => {
+                write!(f, "(interpolate")?;
+                for part in parts {
+                    write!(f, " {}", part)?;
+                }
+                write!(f, ")")
+            },
         }
     }
 }
@@ -696,8 +853,68 @@ impl std::fmt::Debug for Node {
                 position,
             } => write!(f, "({} {:?} {:?})", operator, left, right),
             Node::Parenthesis(e) => write!(f, "(group {:?})", e),
-            Node::Identifier(i, _) => write!(f, "_{}", i),
-            Node::Assignment(i, v, _) => write!(f, "{} = {}", i, v)
+            Node::Identifier(i, _, _) => write!(f, "_{}", i),
+            Node::Assignment(i, v, _, _) => write!(f, "{} = {}", i, v),
+            Node::Call { callee, arguments, .. } => {
+                write!(f, "(call {:?}", callee)?;
+                for arg in arguments {
+                    write!(f, " {:?}", arg)?;
+                }
+                write!(f, ")")
+            },
+            Node::Get(object, name, _) => write!(f, "{:?}.{}", object, name),
+            Node::Set(object, name, value, _) => write!(f, "{:?}.{} = {:?}", object, name, value),
+            Node::This(_) => write!(f, "this"),
+            Node::Super(method, _) => write!(f, "super.{}", method),
+            Node::Interpolation(parts, _) => {
+                write!(f, "(interpolate")?;
+                for part in parts {
+                    write!(f, " {:?}", part)?;
+                }
+                write!(f, ")")
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::AstFactory;
+    use crate::{scanner, source_map::SourceFile};
+
+    /// Line and message of every syntax error in `source`.
+    fn errors(source: &str) -> Vec<(usize, String)> {
+        let file = SourceFile::new("", source);
+        let (_, errors) = AstFactory::new(scanner::scan(source.to_string())).parse_statements();
+        errors.into_iter().map(|e| (file.line(e.span.start), e.message)).collect()
+    }
+
+    fn error(line: usize, message: &str) -> (usize, String) {
+        (line, message.to_string())
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let source = "print (1 + ;\nprint (2 * ;\nvar = 3;\nprint 4 +;\nprint \"ok\";\n";
+        assert_eq!(errors(source), [
+            error(1, "Expect expression."),
+            error(2, "Expect expression."),
+            error(3, "Expect variable name."),
+            error(4, "Expect expression."),
+        ]);
+    }
+
+    #[test]
+    fn unclosed_parenthesis() {
+        assert_eq!(errors("print (1 2);"), [error(1, "Expect ')' after expression.")]);
+        assert_eq!(errors("print (1;\nprint 2;"), [error(1, "Expect ')' after expression.")]);
+        assert_eq!(errors("print ((1 + 2) * (3));"), []);
+    }
+
+    #[test]
+    fn missing_right_operand() {
+        assert_eq!(errors("print 1 +"), [error(1, "Expect expression.")]);
+        assert_eq!(errors("print true and"), [error(1, "Expect expression.")]);
+        assert_eq!(errors("print 2 ^"), [error(1, "Expect expression.")]);
+    }
+}
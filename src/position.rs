@@ -1,29 +1,31 @@
-use crate::parser::Node;
+use crate::{
+    parser::{Node, Statement},
+    source_map::FileId,
+};
 
-
-
-#[derive(Clone)]
+/// A span of source text as byte offsets into one file, `end` exclusive.
+/// Lines and columns come from the file's `SourceFile` when needed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Position {
-    pub from: (usize, usize),
-    pub to: (usize, usize)
+    pub file: FileId,
+    pub start: usize,
+    pub end: usize,
 }
 
 impl Position {
-    pub fn new(line1: usize, col1: usize, line2: usize, col2: usize) -> Position {
+    pub fn new(file: FileId, start: usize, end: usize) -> Position {
         Self {
-            from: (line1, col1),
-            to: (line2, col2)
+            file,
+            start,
+            end,
         }
     }
     pub fn range(from: Self, to: Self) -> Self {
         Self {
-            from: from.from,
-            to: to.to
+            end: to.end.max(from.start),
+            ..from
         }
     }
-    pub fn line(&self) -> usize {
-        self.to.0
-    }
 }
 
 impl Node {
@@ -34,8 +36,35 @@ impl Node {
             Self::Unary(_, _, position) => position.clone(),
             Self::Litteral(_, position) => position.clone(),
             Self::Parenthesis(child) => child.position(),
-            Self::Identifier(_, pos) => pos.clone(),
-            Self::Assignment(_, _, pos) => pos.clone()
+            Self::Identifier(_, _, pos) => pos.clone(),
+            Self::Assignment(_, _, _, pos) => pos.clone(),
+            Self::Call { position, .. } => position.clone(),
+            Self::Get(_, _, pos) => pos.clone(),
+            Self::Set(_, _, _, pos) => pos.clone(),
+            Self::This(pos) => pos.clone(),
+            Self::Super(_, pos) => pos.clone(),
+            Self::Interpolation(_, pos) => pos.clone(),
+        }
+    }
+}
+
+impl Statement {
+    /// Where the statement starts, taken from its first expression or
+    /// declaration. Empty blocks have no position.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Self::Expression(expr) | Self::Print(expr) => Some(expr.position()),
+            Self::Return(_, position) => Some(position.clone()),
+            Self::VarDecl(_, _, position) => Some(position.clone()),
+            Self::If(condition, _, _) | Self::While(condition, _) => Some(condition.position()),
+            Self::For(init, condition, increment, body) => init.as_ref()
+                .and_then(|init| init.position())
+                .or_else(|| condition.as_ref().map(Node::position))
+                .or_else(|| increment.as_ref().map(Node::position))
+                .or_else(|| body.position()),
+            Self::Block(statements) => statements.first().and_then(Statement::position),
+            Self::Function(function) => Some(function.position.clone()),
+            Self::Class(_, _, _, position) => Some(position.clone()),
         }
     }
 }
@@ -0,0 +1,34 @@
+use std::io::{BufRead, Write};
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+/// Reads one `Content-Length` framed JSON message, as used by both the
+/// debug adapter and the language server. Returns `None` at end of input.
+pub fn read_message(input: &mut dyn BufRead) -> anyhow::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let length = length.ok_or_else(|| anyhow!("Missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub fn write_message(output: &mut dyn Write, message: &Value) -> anyhow::Result<()> {
+    let body = serde_json::to_string(message)?;
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
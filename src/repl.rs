@@ -0,0 +1,170 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    lox::{Capture, Lox},
+    parser::Statement,
+    scanner::{self, TokenType},
+};
+
+/// Line-oriented read-eval-print loop. One `Lox` instance lives for the
+/// whole session, so declarations carry over between inputs.
+pub struct Repl {
+    lox: Lox,
+    buffer: String,
+    /// Where output goes when it isn't stdout, kept across `reset`.
+    capture: Option<Capture>,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            lox: Lox::new(),
+            buffer: String::new(),
+            capture: None,
+        }
+    }
+    /// Starts a session whose output, including echoed values, is kept in
+    /// memory.
+    pub fn capture_output() -> (Self, Capture) {
+        let (lox, capture) = Lox::capture_output();
+        let repl = Self {
+            lox,
+            buffer: String::new(),
+            capture: Some(capture.clone()),
+        };
+        (repl, capture)
+    }
+    /// `> ` for a new input, `. ` while continuing an unfinished one.
+    pub fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() { "> " } else { ". " }
+    }
+    /// Reads lines from `input` until it is exhausted.
+    pub fn run(&mut self, mut input: impl BufRead) -> std::io::Result<()> {
+        loop {
+            print!("{}", self.prompt());
+            std::io::stdout().flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                println!();
+                return Ok(());
+            }
+            self.push_line(&line);
+        }
+    }
+    /// Buffers `line` and evaluates the buffer once it forms a complete input.
+    pub fn push_line(&mut self, line: &str) {
+        self.buffer.push_str(line);
+        if !line.ends_with('\n') {
+            self.buffer.push('\n');
+        }
+        if !is_complete(&self.buffer) {
+            return;
+        }
+        let source = std::mem::take(&mut self.buffer);
+        self.eval("", &source);
+    }
+    /// Runs `source`, named `name` in diagnostics, printing the value of
+    /// every top-level bare expression and reporting errors without ending
+    /// the session.
+    pub fn eval(&mut self, name: &str, source: &str) {
+        let result = self.lox.compile(name, source).and_then(|statements| {
+            let statements = statements.into_iter()
+                .map(|statement| match statement {
+                    Statement::Expression(expr) => Statement::Print(expr),
+                    statement => statement,
+                })
+                .collect();
+            self.lox.eval_statements(statements)
+        });
+        if let Err(e) = result {
+            eprint!("{}", self.lox.sources().render(&e));
+        }
+    }
+    /// Drops any unfinished input.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+    /// Starts over with a fresh interpreter.
+    pub fn reset(&mut self) {
+        self.lox = match &self.capture {
+            Some(capture) => Lox::with_output(capture.clone()),
+            None => Lox::new(),
+        };
+        self.buffer.clear();
+    }
+    pub fn lox(&mut self) -> &mut Lox {
+        &mut self.lox
+    }
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `source` can be run as is: every `{` is closed and no string
+/// literal is left open.
+pub fn is_complete(source: &str) -> bool {
+    let mut depth = 0;
+    for token in scanner::scan(source.to_string()) {
+        match token.token_type {
+            TokenType::LeftBrace => depth += 1,
+            TokenType::RightBrace => depth -= 1,
+            TokenType::Invalid(message) if message.starts_with("Unterminated") => return false,
+            _ => {},
+        }
+    }
+    depth <= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_complete, Repl};
+
+    #[test]
+    fn complete_inputs() {
+        assert!(is_complete("print 1;"));
+        assert!(is_complete("{ { } }"));
+        assert!(is_complete("print \"}\";"));
+        assert!(!is_complete("fun f() {"));
+        assert!(!is_complete("if (true) { {\n}"));
+        assert!(!is_complete("print \"abc"));
+        assert!(!is_complete("/* still open"));
+        assert!(!is_complete("print \"${"));
+    }
+
+    #[test]
+    fn prints_every_bare_expression() {
+        let (mut repl, output) = Repl::capture_output();
+        repl.push_line("1; print 2; \"three\";");
+        assert_eq!(output.take(), "1\n2\nthree\n");
+        repl.push_line("var a = 4;");
+        assert_eq!(output.take(), "");
+    }
+
+    #[test]
+    fn multi_line_input() {
+        let (mut repl, output) = Repl::capture_output();
+        repl.push_line("fun f() {");
+        assert_eq!(repl.prompt(), ". ");
+        repl.push_line("  return 1;");
+        repl.push_line("}");
+        assert_eq!(repl.prompt(), "> ");
+        repl.push_line("f();");
+        assert_eq!(output.take(), "1\n");
+    }
+
+    #[test]
+    fn state_survives_errors() {
+        let (mut repl, output) = Repl::capture_output();
+        repl.push_line("var a = 1;");
+        repl.push_line("a; a + b; a;");
+        assert_eq!(output.take(), "1\n");
+        repl.push_line("a;");
+        assert_eq!(output.take(), "1\n");
+        repl.reset();
+        repl.push_line("2;");
+        assert_eq!(output.take(), "2\n");
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use crate::{diagnostics::{self, Diagnostic}, parser::{Function, Node, Statement}, position::Position};
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+/// Static pass run between parsing and interpreting. Annotates every local
+/// `Identifier` and `Assignment` with the number of scopes between its use
+/// and its declaration, and reports misuse of variables and `return`.
+pub struct Resolver {
+    scopes: Vec<HashMap<String, bool>>,
+    function: FunctionType,
+    class: ClassType,
+    errors: Vec<Diagnostic>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            function: FunctionType::None,
+            class: ClassType::None,
+            errors: Vec::new(),
+        }
+    }
+    pub fn resolve(&mut self, statements: &mut [Statement]) -> Result<(), Vec<Diagnostic>> {
+        for stmt in statements.iter_mut() {
+            self.resolve_statement(stmt);
+        }
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+    fn resolve_statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::Expression(expr) | Statement::Print(expr) => self.resolve_expr(expr),
+            Statement::VarDecl(name, initializer, position) => {
+                self.declare(name, position);
+                self.resolve_expr(initializer);
+                self.define(name);
+            },
+            Statement::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                for stmt in statements.iter_mut() {
+                    self.resolve_statement(stmt);
+                }
+                self.scopes.pop();
+            },
+            Statement::If(condition, then, els) => {
+                self.resolve_expr(condition);
+                self.resolve_statement(then);
+                if let Some(els) = els {
+                    self.resolve_statement(els);
+                }
+            },
+            Statement::While(condition, body) => {
+                self.resolve_expr(condition);
+                self.resolve_statement(body);
+            },
+            Statement::For(init, con, inc, body) => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.resolve_statement(init);
+                }
+                if let Some(con) = con {
+                    self.resolve_expr(con);
+                }
+                if let Some(inc) = inc {
+                    self.resolve_expr(inc);
+                }
+                self.resolve_statement(body);
+                self.scopes.pop();
+            },
+            Statement::Function(function) => {
+                self.declare(&function.name, &function.position);
+                self.define(&function.name);
+                self.resolve_function(function, FunctionType::Function);
+            },
+            Statement::Return(value, position) => {
+                if self.function == FunctionType::None {
+                    self.error(diagnostics::INVALID_RETURN, position, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    if self.function == FunctionType::Initializer {
+                        self.error(diagnostics::INVALID_RETURN, &value.position(), "Can't return a value from an initializer.");
+                    }
+                    self.resolve_expr(value);
+                }
+            },
+            Statement::Class(name, superclass, methods, position) => {
+                let enclosing = self.class;
+                self.class = ClassType::Class;
+                self.declare(name, position);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    self.class = ClassType::Subclass;
+                    self.resolve_expr(superclass);
+                    self.scopes.push(HashMap::from([("super".to_string(), true)]));
+                }
+                self.scopes.push(HashMap::from([("this".to_string(), true)]));
+                for method in methods.iter_mut() {
+                    let kind = if method.name == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.resolve_function(method, kind);
+                }
+                self.scopes.pop();
+                if superclass.is_some() {
+                    self.scopes.pop();
+                }
+                self.class = enclosing;
+            },
+        }
+    }
+    fn resolve_function(&mut self, function: &mut Function, kind: FunctionType) {
+        let enclosing = self.function;
+        self.function = kind;
+        self.scopes.push(HashMap::new());
+        for (param, position) in function.params.iter().zip(&function.param_positions) {
+            self.declare(param, position);
+            self.define(param);
+        }
+        for stmt in function.body.iter_mut() {
+            self.resolve_statement(stmt);
+        }
+        self.scopes.pop();
+        self.function = enclosing;
+    }
+    fn resolve_expr(&mut self, expr: &mut Node) {
+        match expr {
+            Node::Binary { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            },
+            Node::Parenthesis(inner) | Node::Unary(_, inner, _) => self.resolve_expr(inner),
+            Node::Litteral(_, _) => {},
+            Node::Identifier(name, depth, position) => {
+                if let Some(false) = self.scopes.last().and_then(|scope| scope.get(name.as_str())) {
+                    let message = format!("Can't read local variable '{}' in its own initializer.", name);
+                    self.error(diagnostics::OWN_INITIALIZER, position, &message);
+                }
+                *depth = self.resolve_local(name);
+            },
+            Node::Assignment(name, value, depth, _) => {
+                self.resolve_expr(value);
+                *depth = self.resolve_local(name);
+            },
+            Node::Call { callee, arguments, .. } => {
+                self.resolve_expr(callee);
+                for argument in arguments.iter_mut() {
+                    self.resolve_expr(argument);
+                }
+            },
+            Node::Interpolation(parts, _) => {
+                for part in parts.iter_mut() {
+                    self.resolve_expr(part);
+                }
+            },
+            Node::Get(object, _, _) => self.resolve_expr(object),
+            Node::Set(object, _, value, _) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            },
+            Node::This(position) => {
+                if self.class == ClassType::None {
+                    self.error(diagnostics::INVALID_THIS, position, "Can't use 'this' outside of a class.");
+                }
+            },
+            Node::Super(_, position) => match self.class {
+                ClassType::None => {
+                    self.error(diagnostics::INVALID_SUPER, position, "Can't use 'super' outside of a class.");
+                },
+                ClassType::Class => {
+                    self.error(diagnostics::INVALID_SUPER, position, "Can't use 'super' in a class with no superclass.");
+                },
+                ClassType::Subclass => {},
+            },
+        }
+    }
+    /// Number of scopes between the innermost scope and the one declaring
+    /// `name`, or `None` when it must be a global.
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rev().position(|scope| scope.contains_key(name))
+    }
+    fn declare(&mut self, name: &str, position: &Position) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.insert(name.to_string(), false).is_some() {
+            let message = format!("Already a variable named '{}' in this scope.", name);
+            self.error(diagnostics::REDECLARED_LOCAL, position, &message);
+        }
+    }
+    fn define(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), true);
+        }
+    }
+    fn error(&mut self, code: &'static str, position: &Position, message: &str) {
+        self.errors.push(Diagnostic::error(code, message, position.clone()));
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile, diagnostics, error::LoxError, parser::{Node, Statement}};
+
+    /// Codes and messages of the static errors `source` produces.
+    fn errors(source: &str) -> Vec<(&'static str, String)> {
+        match compile(source) {
+            Ok(_) => Vec::new(),
+            Err(LoxError::ParseError(errors)) => errors.into_iter().map(|e| (e.code, e.message)).collect(),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    fn error(code: &'static str, message: &str) -> Vec<(&'static str, String)> {
+        vec![(code, message.to_string())]
+    }
+
+    #[test]
+    fn reads_in_own_initializer() {
+        assert_eq!(
+            errors("{ var a = a; }"),
+            error(diagnostics::OWN_INITIALIZER, "Can't read local variable 'a' in its own initializer.")
+        );
+        assert_eq!(errors("var a = 1; var a = a;"), Vec::new());
+    }
+
+    #[test]
+    fn redeclared_locals() {
+        assert_eq!(
+            errors("{ var a = 1; var a = 2; }"),
+            error(diagnostics::REDECLARED_LOCAL, "Already a variable named 'a' in this scope.")
+        );
+        assert_eq!(
+            errors("fun f(a, a) {}"),
+            error(diagnostics::REDECLARED_LOCAL, "Already a variable named 'a' in this scope.")
+        );
+        assert_eq!(errors("var a = 1; var a = 2; { var a = 3; }"), Vec::new());
+    }
+
+    #[test]
+    fn top_level_return() {
+        assert_eq!(errors("return;"), error(diagnostics::INVALID_RETURN, "Can't return from top-level code."));
+        assert_eq!(errors("return 1;"), error(diagnostics::INVALID_RETURN, "Can't return from top-level code."));
+        assert_eq!(errors("fun f() { return 1; }"), Vec::new());
+    }
+
+    #[test]
+    fn return_value_from_initializer() {
+        let message = "Can't return a value from an initializer.";
+        assert_eq!(errors("class A { init() { return 1; } }"), error(diagnostics::INVALID_RETURN, message));
+        assert_eq!(errors("class A { init() { return nil; } }"), error(diagnostics::INVALID_RETURN, message));
+        assert_eq!(errors("class A { init() { return; } }"), Vec::new());
+        assert_eq!(errors("class A { init() { fun f() { return 1; } } }"), Vec::new());
+        assert_eq!(errors("class A { method() { return 1; } }"), Vec::new());
+    }
+
+    #[test]
+    fn this_outside_class() {
+        let message = "Can't use 'this' outside of a class.";
+        assert_eq!(errors("print this;"), error(diagnostics::INVALID_THIS, message));
+        assert_eq!(errors("fun f() { return this; }"), error(diagnostics::INVALID_THIS, message));
+        assert_eq!(errors("class A { f() { return this; } }"), Vec::new());
+    }
+
+    #[test]
+    fn invalid_super() {
+        assert_eq!(
+            errors("super.f();"),
+            error(diagnostics::INVALID_SUPER, "Can't use 'super' outside of a class.")
+        );
+        assert_eq!(
+            errors("class A { f() { super.f(); } }"),
+            error(diagnostics::INVALID_SUPER, "Can't use 'super' in a class with no superclass.")
+        );
+        assert_eq!(errors("class A {} class B < A { f() { super.f(); } }"), Vec::new());
+    }
+
+    #[test]
+    fn reports_every_error() {
+        assert_eq!(errors("return; print this;").len(), 2);
+    }
+
+    #[test]
+    fn resolves_local_depths() {
+        let statements = compile("var g; { var a; { a; g; } }").unwrap();
+        let Statement::Block(outer) = &statements[1] else { panic!() };
+        let Statement::Block(inner) = &outer[1] else { panic!() };
+        let depth = |statement: &Statement| match statement {
+            Statement::Expression(Node::Identifier(_, depth, _)) => *depth,
+            _ => panic!(),
+        };
+        assert_eq!(depth(&inner[0]), Some(1));
+        assert_eq!(depth(&inner[1]), None);
+    }
+}
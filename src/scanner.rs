@@ -1,6 +1,10 @@
 use std::fmt::Display;
 
-use crate::position::Position;
+use crate::{
+    diagnostics::{self, Diagnostic},
+    position::Position,
+    source_map::FileId,
+};
 
 #[derive(Clone, Debug, PartialEq)]
 #[allow(unused)]
@@ -48,6 +52,14 @@ pub enum TokenType {
     StringLitteral(String),
     Identifier(String),
     Invalid(String),
+
+    /// The part of an interpolated string before a `${`. The part after the
+    /// last `}` is an ordinary `StringLitteral`.
+    Interpolation(String),
+
+    /// A `//` or `/* */` comment, without its delimiters. Only kept by
+    /// `scan_with_trivia`.
+    Comment(String),
 }
 #[derive(Clone)]
 pub struct Token {
@@ -56,87 +68,126 @@ pub struct Token {
     pub position: Position,
 }
 
-pub fn scan(str: String) -> anyhow::Result<Vec<Token>> {
+/// Splits `str` into tokens. Scanning never fails: malformed input becomes
+/// `Invalid` tokens, which `Token::diagnostic` turns into errors.
+pub fn scan(str: String) -> Vec<Token> {
+    scan_file(FileId::default(), str)
+}
+
+/// Like `scan`, with token positions pointing into `file`.
+pub fn scan_file(file: FileId, str: String) -> Vec<Token> {
+    let mut tokens = tokenize(file, str);
+    tokens.retain(|token| !token.is_trivia());
+    tokens
+}
+
+/// Like `scan`, but keeps comments as `Comment` tokens.
+pub fn scan_with_trivia(str: String) -> Vec<Token> {
+    tokenize(FileId::default(), str)
+}
+
+fn tokenize(file: FileId, str: String) -> Vec<Token> {
     let mut out = Vec::new();
     let mut buffer: String = String::new();
-    let mut line_number = 1;
-    let mut new_line = 0;
     let stream: Vec<char> = str.chars().collect();
+    // Byte offset of each char, plus one past the end.
+    let offsets: Vec<usize> = str.char_indices().map(|(offset, _)| offset).chain([str.len()]).collect();
+    let span = |from: usize, to: usize| Position::new(file, offsets[from], offsets[to]);
+    // Open `${` interpolations: braces opened inside each one, and where it starts.
+    let mut interpolations: Vec<(usize, Position)> = Vec::new();
     let mut i = 0;
     while i < stream.len() {
         match stream[i] {
-            ' ' | '\t' => {},
-            '\n' => {
-                line_number += 1;
-                new_line = i;
-            },
-            x if x.is_numeric() => {
-                let mut float = false;
-                let first_line = line_number;
-                let first_col = i - new_line;
+            ' ' | '\t' | '\n' => {},
+            x if x.is_ascii_digit() => {
+                let start = i;
+                let token_type = match (x, stream.get(i + 1)) {
+                    ('0', Some('x' | 'X')) => radix_number(&stream, &mut i, 16),
+                    ('0', Some('b' | 'B')) => radix_number(&stream, &mut i, 2),
+                    ('0', Some('o' | 'O')) => radix_number(&stream, &mut i, 8),
+                    _ => decimal_number(&stream, &mut i),
+                };
+                let token = Token {
+                    token_type,
+                    raw: stream[start..i].iter().collect(),
+                    position: span(start, i),
+                };
+                i -= 1;
+                out.push(token);
+            }
+            c if c == '"'
+                || raw_string_hashes(&stream, i).is_some()
+                || (c == '}' && interpolations.last().is_some_and(|(depth, _)| *depth == 0)) => {
+                let start = i;
+                let resumed = c == '}';
+                if resumed {
+                    interpolations.pop();
+                }
+                let hashes = raw_string_hashes(&stream, i).filter(|_| !resumed);
+                i += hashes.map_or(1, |hashes| hashes + 2);
+                let mut closing = vec!['"'];
+                closing.extend(std::iter::repeat('#').take(hashes.unwrap_or(0)));
+                let mut errors: Vec<Token> = Vec::new();
                 loop {
-                    if i != stream.len() && stream[i] == '.' && !float {
-                        float = true;
-                        buffer.push('.');
-                        i+=1;
-                        continue;
-                    }
-                    if i == stream.len() || !stream[i].is_numeric() {
-
-                        let number = buffer.clone().parse::<f64>()?;
+                    if i == stream.len() {
                         let token = Token {
-                            token_type: TokenType::Number(number),
-                            raw: buffer.clone(),
-                            position: Position::new(first_line, first_col, line_number, i - new_line),
+                            token_type: TokenType::Invalid("Unterminated string.".to_string()),
+                            raw: stream[start..].iter().collect(),
+                            position: span(start, i),
                         };
                         buffer.clear();
-                        i -= 1;
                         out.push(token);
                         break;
                     }
-                    buffer.push(stream[i]);
-                    i += 1;
-                }
-            }
-            '"' => {
-                i += 1;
-                let first_line = line_number;
-                let first_col = i - new_line;
-                loop {
-                    if i == stream.len() {
+                    if stream[i..].starts_with(&closing) {
+                        i += closing.len() - 1;
                         let token = Token {
-                            token_type: TokenType::Invalid(format!("Unterminated string.")),
-                            raw: buffer.clone(),
-                            position: Position::new(
-                                first_line, 
-                                first_col, 
-                                line_number, 
-                                i - new_line
-                            ),
+                            token_type: TokenType::StringLitteral(buffer.clone()),
+                            raw: stream[start..=i].iter().collect(),
+                            position: span(start, i + 1),
                         };
-                        out.push(token);
+                        buffer.clear();
+                        if errors.is_empty() {
+                            out.push(token);
+                        } else {
+                            out.append(&mut errors);
+                        }
                         break;
                     }
-                    if stream[i] == '"' {
+                    if stream[i] == '$' && stream.get(i + 1) == Some(&'{') && hashes.is_none() {
+                        i += 1;
                         let token = Token {
-                            token_type: TokenType::StringLitteral(buffer.clone()),
-                            raw: format!("\"{}\"", buffer.clone()),
-                            position: Position::new(
-                                first_line, 
-                                first_col, 
-                                line_number, 
-                                i - new_line
-                            ),
+                            token_type: TokenType::Interpolation(buffer.clone()),
+                            raw: stream[start..=i].iter().collect(),
+                            position: span(start, i + 1),
                         };
                         buffer.clear();
-                        out.push(token);
+                        if errors.is_empty() {
+                            out.push(token);
+                        } else {
+                            out.append(&mut errors);
+                        }
+                        interpolations.push((0, span(i - 1, i + 1)));
                         break;
                     }
-                    buffer.push(stream[i]);
-                    if stream[i] == '\n' {
-                        line_number += 1;
-                        new_line = i;
+                    if stream[i] == '\\' && hashes.is_none() {
+                        match escape(&stream[i..]) {
+                            Ok((c, length)) => {
+                                buffer.push(c);
+                                i += length;
+                            },
+                            Err((message, length)) => {
+                                errors.push(Token {
+                                    token_type: TokenType::Invalid(message),
+                                    raw: stream[i..i + length].iter().collect(),
+                                    position: span(i, i + length),
+                                });
+                                i += 1;
+                            },
+                        }
+                        continue;
                     }
+                    buffer.push(stream[i]);
                     i += 1;
                 }
             }
@@ -151,29 +202,79 @@ pub fn scan(str: String) -> anyhow::Result<Vec<Token>> {
                 let token = Token {
                     token_type,
                     raw: format!("{}{}", stream[i], stream[i+1]),
-                    position: Position::new(line_number, i, line_number, i+1),
+                    position: span(i, i + 2),
                 };
                 out.push(token);
                 i += 1;
             }
             '/' if i+1 < stream.len() && stream[i+1] == '/' => {
+                let start = i;
                 loop {
                     if i == stream.len() || stream[i] == '\n' {
-                        line_number += 1;
+                        let token = Token {
+                            token_type: TokenType::Comment(buffer[2..].to_string()),
+                            raw: buffer.clone(),
+                            position: span(start, i),
+                        };
+                        buffer.clear();
+                        out.push(token);
+                        i -= 1;
                         break;
                     }
+                    buffer.push(stream[i]);
+                    i += 1;
+                }
+            }
+            '/' if i+1 < stream.len() && stream[i+1] == '*' => {
+                let start = i;
+                let mut depth = 0;
+                loop {
+                    if i == stream.len() {
+                        let token = Token {
+                            token_type: TokenType::Invalid("Unterminated block comment.".to_string()),
+                            raw: buffer.clone(),
+                            position: span(start, start + 2),
+                        };
+                        buffer.clear();
+                        out.push(token);
+                        break;
+                    }
+                    if stream[i] == '/' && i+1 < stream.len() && stream[i+1] == '*' {
+                        depth += 1;
+                        buffer.push_str("/*");
+                        i += 2;
+                        continue;
+                    }
+                    if stream[i] == '*' && i+1 < stream.len() && stream[i+1] == '/' {
+                        depth -= 1;
+                        buffer.push_str("*/");
+                        i += 1;
+                        if depth == 0 {
+                            let token = Token {
+                                token_type: TokenType::Comment(buffer[2..buffer.len() - 2].to_string()),
+                                raw: buffer.clone(),
+                                position: span(start, i + 1),
+                            };
+                            buffer.clear();
+                            out.push(token);
+                            break;
+                        }
+                        i += 1;
+                        continue;
+                    }
+                    buffer.push(stream[i]);
                     i += 1;
                 }
             }
             c if c.is_alphabetic() || c == '_' => {
-                let first_col = i - new_line;
+                let start = i;
                 loop {
                     if i == stream.len()
                     || !(stream[i].is_alphanumeric() || stream[i] == '_') {
                         let token = Token {
                             token_type: TokenType::from(buffer.clone()),
                             raw: buffer.clone(),
-                            position: Position::new(line_number, first_col, line_number, i - new_line)
+                            position: span(start, i)
                         };
                         buffer.clear();
                         out.push(token);
@@ -185,17 +286,128 @@ pub fn scan(str: String) -> anyhow::Result<Vec<Token>> {
                 }
             }
             _ => {
+                if let Some((depth, _)) = interpolations.last_mut() {
+                    match stream[i] {
+                        '{' => *depth += 1,
+                        '}' => *depth -= 1,
+                        _ => {},
+                    }
+                }
                 let token = Token {
                     token_type: TokenType::from(stream[i]),
                     raw: format!("{}", stream[i]),
-                    position: Position::new(line_number, i - new_line, line_number, i - new_line)
+                    position: span(i, i + 1)
                 };
                 out.push(token);
             }
         }
         i += 1;
     }
-    Ok(out)
+    for (_, position) in interpolations {
+        out.push(Token {
+            token_type: TokenType::Invalid("Unterminated string interpolation.".to_string()),
+            raw: "${".to_string(),
+            position,
+        });
+    }
+    out
+}
+
+/// Scans the digits of a `0x`, `0b` or `0o` integer starting at `i`,
+/// leaving `i` just past the literal.
+fn radix_number(stream: &[char], i: &mut usize, radix: u32) -> TokenType {
+    let start = *i;
+    *i += 2;
+    while *i < stream.len() && (stream[*i].is_ascii_alphanumeric() || stream[*i] == '_') {
+        *i += 1;
+    }
+    let raw: String = stream[start..*i].iter().collect();
+    let digits = &raw[2..];
+    let kind = match radix {
+        16 => "hexadecimal",
+        8 => "octal",
+        _ => "binary",
+    };
+    match u64::from_str_radix(&digits.replace('_', ""), radix) {
+        Ok(value) if valid_separators(digits) => TokenType::Number(value as f64),
+        _ => TokenType::Invalid(format!("Invalid {} literal '{}'.", kind, raw)),
+    }
+}
+
+/// Scans a decimal literal with optional fraction and exponent starting at
+/// `i`, leaving `i` just past the literal. A `.` or `e` not followed by a
+/// digit is left for the next token.
+fn decimal_number(stream: &[char], i: &mut usize) -> TokenType {
+    let start = *i;
+    let digits = |i: &mut usize| {
+        while *i < stream.len() && (stream[*i].is_ascii_digit() || stream[*i] == '_') {
+            *i += 1;
+        }
+    };
+    let digit_at = |i: usize| stream.get(i).is_some_and(char::is_ascii_digit);
+    digits(i);
+    if stream.get(*i) == Some(&'.') && digit_at(*i + 1) {
+        *i += 1;
+        digits(i);
+    }
+    if matches!(stream.get(*i), Some('e' | 'E')) {
+        let sign = usize::from(matches!(stream.get(*i + 1), Some('+' | '-')));
+        if digit_at(*i + 1 + sign) {
+            *i += 1 + sign;
+            digits(i);
+        }
+    }
+    let raw: String = stream[start..*i].iter().collect();
+    let parts_valid = raw.split(['.', 'e', 'E', '+', '-']).all(valid_separators);
+    match raw.replace('_', "").parse::<f64>() {
+        Ok(value) if parts_valid => TokenType::Number(value),
+        _ => TokenType::Invalid(format!("Invalid number literal '{}'.", raw)),
+    }
+}
+
+/// Whether `_` only appears between two digits in `digits`.
+fn valid_separators(digits: &str) -> bool {
+    !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+}
+
+/// Number of `#` in the `r#"` that opens a raw string at `i`, if one does.
+fn raw_string_hashes(stream: &[char], i: usize) -> Option<usize> {
+    if stream.get(i) != Some(&'r') {
+        return None;
+    }
+    let hashes = stream[i + 1..].iter().take_while(|c| **c == '#').count();
+    (stream.get(i + 1 + hashes) == Some(&'"')).then_some(hashes)
+}
+
+/// Decodes the escape sequence at the start of `stream`, returning the
+/// character and how many characters it spans, or an error message and
+/// the length of the invalid sequence.
+fn escape(stream: &[char]) -> Result<(char, usize), (String, usize)> {
+    match stream.get(1) {
+        Some('n') => Ok(('\n', 2)),
+        Some('t') => Ok(('\t', 2)),
+        Some('\\') => Ok(('\\', 2)),
+        Some('"') => Ok(('"', 2)),
+        Some('$') => Ok(('$', 2)),
+        Some('u') => {
+            let digits: String = stream.iter().skip(3).take_while(|c| c.is_ascii_hexdigit()).collect();
+            let length = 3 + digits.len() + 1;
+            let closed = stream.get(2) == Some(&'{') && stream.get(length - 1) == Some(&'}');
+            let c = u32::from_str_radix(&digits, 16).ok()
+                .filter(|_| closed && (1..=6).contains(&digits.len()))
+                .and_then(char::from_u32);
+            match c {
+                Some(c) => Ok((c, length)),
+                None => {
+                    let length = if closed { length } else { 2 };
+                    let sequence: String = stream[..length].iter().collect();
+                    Err((format!("Invalid unicode escape '{}'.", sequence), length))
+                },
+            }
+        },
+        Some(c) if *c != '\n' => Err((format!("Invalid escape sequence '\\{}'.", c), 2)),
+        _ => Err(("Invalid escape sequence '\\'.".to_string(), 1)),
+    }
 }
 
 impl Display for Token {
@@ -203,8 +415,7 @@ impl Display for Token {
         use TokenType::*;
         let str: &str = match self.token_type.clone() {
             Invalid(err) => {
-                eprint!("[line {}]: Error: {}", self.position.line(), err);
-                return Ok(());
+                return write!(f, "Error: {}", err);
             },
             LeftParen => "LEFT_PAREN",
             RightParen => "RIGHT_PAREN",
@@ -247,12 +458,14 @@ impl Display for Token {
 
             Number(_) => "NUMBER",
             StringLitteral(_) => "STRING",
-            Identifier(_) => "IDENTIFIER"
+            Interpolation(_) => "INTERPOLATION",
+            Identifier(_) => "IDENTIFIER",
+            Comment(_) => "COMMENT",
         };
         let inner = match self.token_type.clone() {
             Number(n) => format!("{:?}", n),
-            StringLitteral(s) => format!("{}", s),
-            _ => format!("null")
+            StringLitteral(s) | Interpolation(s) => s.to_string(),
+            _ => "null".to_string()
         };
         write!(f, "{} {} {}", str, self.raw, inner)?;
         Ok(())
@@ -311,11 +524,171 @@ impl From<String> for TokenType {
 
 impl Token {
     pub fn is_valid(&self) -> bool {
-        match self.token_type {
-            TokenType::Invalid(_) => false,
-            _ => true
+        !matches!(self.token_type, TokenType::Invalid(_))
+    }
+    /// Whether the parser ignores this token.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.token_type, TokenType::Comment(_))
+    }
+    /// The error carried by an `Invalid` token, if this is one.
+    pub fn diagnostic(&self) -> Option<Diagnostic> {
+        match &self.token_type {
+            TokenType::Invalid(message) => Some(Diagnostic::error(
+                diagnostics::INVALID_TOKEN,
+                message.clone(),
+                self.position.clone()
+            )),
+            _ => None,
         }
     }
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::{scan, scan_with_trivia, TokenType::{self, *}};
+
+    fn types(source: &str) -> Vec<TokenType> {
+        scan(source.to_string()).into_iter().map(|token| token.token_type).collect()
+    }
+
+    fn identifier(name: &str) -> TokenType {
+        Identifier(name.to_string())
+    }
+
+    fn string(value: &str) -> TokenType {
+        StringLitteral(value.to_string())
+    }
+
+    fn invalid(message: &str) -> TokenType {
+        Invalid(message.to_string())
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        assert_eq!(types("a /* x /* y */ z */ b"), [identifier("a"), identifier("b")]);
+        let tokens = scan_with_trivia("/* x /* y */ z */".to_string());
+        assert_eq!(tokens[0].token_type, Comment(" x /* y */ z ".to_string()));
+        let tokens = scan("/* one\ntwo */ after".to_string());
+        assert_eq!(tokens[0].position.start, 14);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let tokens = scan("a /* x /* y */".to_string());
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].token_type, Invalid("Unterminated block comment.".to_string()));
+        assert_eq!((tokens[1].position.start, tokens[1].position.end), (2, 4));
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(types(r#""\n\t\\\"\$""#), [string("\n\t\\\"$")]);
+        assert_eq!(types(r#""\u{41}\u{e9}\u{1F600}""#), [string("Aé😀")]);
+        assert_eq!(types("\"two\nlines\""), [string("two\nlines")]);
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        let tokens = scan(r#"x = "a\qb";"#.to_string());
+        assert_eq!(tokens[2].token_type, invalid(r"Invalid escape sequence '\q'."));
+        assert_eq!((tokens[2].position.start, tokens[2].position.end), (6, 8));
+        assert_eq!(tokens[3].token_type, SemiColon);
+        assert_eq!(types(r#""\u{110000}""#), [invalid(r"Invalid unicode escape '\u{110000}'.")]);
+        assert_eq!(types(r#""\u{}""#), [invalid(r"Invalid unicode escape '\u{}'.")]);
+        assert_eq!(types(r#""\u{1234567}""#), [invalid(r"Invalid unicode escape '\u{1234567}'.")]);
+        assert_eq!(types(r#""\u{41""#), [invalid(r"Invalid unicode escape '\u'.")]);
+        assert_eq!(types(r#""\u41""#), [invalid(r"Invalid unicode escape '\u'.")]);
+        // Every bad escape in a string is reported.
+        assert_eq!(types(r#""\a\b""#), [
+            invalid(r"Invalid escape sequence '\a'."),
+            invalid(r"Invalid escape sequence '\b'."),
+        ]);
+    }
+
+    #[test]
+    fn raw_strings() {
+        assert_eq!(types(r#"r"a\nb""#), [string(r"a\nb")]);
+        assert_eq!(types(r##"r#"say "hi""#"##), [string(r#"say "hi""#)]);
+        assert_eq!(types(r#"r"${x}""#), [string("${x}")]);
+        assert_eq!(types("r\"two\nlines\""), [string("two\nlines")]);
+        assert_eq!(types(r#"r#"open""#), [invalid("Unterminated string.")]);
+        assert_eq!(types("r + r#"), [identifier("r"), Plus, identifier("r"), invalid("Unexpected character: #")]);
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let tokens = scan(r#"print "abc"#.to_string());
+        assert_eq!(tokens[1].token_type, invalid("Unterminated string."));
+        assert_eq!((tokens[1].position.start, tokens[1].position.end), (6, 10));
+    }
+
+    #[test]
+    fn interpolation() {
+        assert_eq!(types(r#""a ${b} c""#), [Interpolation("a ".to_string()), identifier("b"), string(" c")]);
+        assert_eq!(types(r#""${1}${2}""#), [
+            Interpolation("".to_string()),
+            Number(1.0),
+            Interpolation("".to_string()),
+            Number(2.0),
+            string(""),
+        ]);
+        assert_eq!(types(r#""\${b}""#), [string("${b}")]);
+    }
+
+    #[test]
+    fn nested_interpolation() {
+        assert_eq!(types(r#""x ${"in ${y}" + "}"} z""#), [
+            Interpolation("x ".to_string()),
+            Interpolation("in ".to_string()),
+            identifier("y"),
+            string(""),
+            Plus,
+            string("}"),
+            string(" z"),
+        ]);
+    }
+
+    #[test]
+    fn unterminated_interpolation() {
+        let tokens = scan(r#"print "a ${b;"#.to_string());
+        let last = tokens.last().unwrap();
+        assert_eq!(last.token_type, invalid("Unterminated string interpolation."));
+        assert_eq!((last.position.start, last.position.end), (9, 11));
+        assert_eq!(types(r#""${"#), [Interpolation("".to_string()), invalid("Unterminated string interpolation.")]);
+        assert_eq!(types(r#""${b} c"#), [
+            Interpolation("".to_string()),
+            identifier("b"),
+            invalid("Unterminated string."),
+        ]);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(types("1_000 1e+5 2.5e-1 1E3"), [Number(1000.0), Number(100000.0), Number(0.25), Number(1000.0)]);
+        assert_eq!(types("0xff 0XFF 0b101 0o17 0x_f"), [
+            Number(255.0),
+            Number(255.0),
+            Number(5.0),
+            Number(15.0),
+            invalid("Invalid hexadecimal literal '0x_f'."),
+        ]);
+        // `.` and `e` without a digit after them belong to the next token.
+        assert_eq!(types("1.foo 2e"), [Number(1.0), Dot, identifier("foo"), Number(2.0), identifier("e")]);
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        assert_eq!(types("0x_ff"), [invalid("Invalid hexadecimal literal '0x_ff'.")]);
+        assert_eq!(types("0b102"), [invalid("Invalid binary literal '0b102'.")]);
+        assert_eq!(types("0o8"), [invalid("Invalid octal literal '0o8'.")]);
+        assert_eq!(types("0x"), [invalid("Invalid hexadecimal literal '0x'.")]);
+        assert_eq!(types("1_"), [invalid("Invalid number literal '1_'.")]);
+        assert_eq!(types("1__0"), [invalid("Invalid number literal '1__0'.")]);
+        assert_eq!(types("1_.5"), [invalid("Invalid number literal '1_.5'.")]);
+        assert_eq!(types("1e_5"), [Number(1.0), identifier("e_5")]);
+        // Only ASCII digits start a number.
+        assert_eq!(types("٣"), [invalid("Unexpected character: ٣")]);
+    }
+}
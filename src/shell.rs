@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{
+    parser::AstFactory,
+    repl::Repl,
+    scanner,
+    source_map::SourceMap,
+};
+
+const HELP: &str = "\
+:env             show the variables in the current environment
+:ast <source>    show the syntax tree of <source>
+:tokens <source> show the tokens of <source>
+:load <file>     run a file in this session
+:reset           start over with a fresh interpreter
+:help            show this message
+:quit            leave the shell";
+
+/// Interactive front end for `Repl` with line editing, a history file and
+/// `:` meta-commands.
+pub struct Shell {
+    repl: Repl,
+    history: Option<PathBuf>,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".lox_history"));
+        Self {
+            repl: Repl::new(),
+            history,
+        }
+    }
+    /// Writes the history after every line so that Ctrl-C, a crash or a
+    /// closed terminal doesn't lose it. A history file that can't be written
+    /// is reported once and then left alone.
+    fn save_history(&mut self, editor: &mut DefaultEditor) {
+        if let Some(history) = &self.history {
+            if let Err(e) = editor.save_history(history) {
+                eprintln!("Failed to save history to {}: {}", history.display(), e);
+                self.history = None;
+            }
+        }
+    }
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        let mut editor = DefaultEditor::new()?;
+        if let Some(history) = &self.history {
+            // A missing history file just means this is the first session.
+            let _ = editor.load_history(history);
+        }
+        loop {
+            match editor.readline(self.repl.prompt()) {
+                Ok(line) => {
+                    editor.add_history_entry(line.as_str())?;
+                    self.save_history(&mut editor);
+                    if self.repl.prompt() == "> " && line.trim_start().starts_with(':') {
+                        if !self.command(line.trim()) {
+                            break;
+                        }
+                    } else {
+                        self.repl.push_line(&line);
+                    }
+                },
+                Err(ReadlineError::Interrupted) => self.repl.clear(),
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+    /// Runs a meta-command. Returns `false` when the shell should exit.
+    fn command(&mut self, line: &str) -> bool {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+        let mut sources = SourceMap::new();
+        let file = sources.add("", argument);
+        match command {
+            ":env" => print!("{}", self.repl.lox().interpreter().environment().borrow()),
+            ":ast" => {
+                let (statements, errors) = AstFactory::new(scanner::scan_file(file, argument.to_string())).parse_statements();
+                for statement in statements {
+                    println!("{}", statement);
+                }
+                for error in errors {
+                    eprint!("{}", sources.render_diagnostic(&error));
+                }
+            },
+            ":tokens" => {
+                for token in scanner::scan_file(file, argument.to_string()) {
+                    match token.diagnostic() {
+                        Some(diagnostic) => eprint!("{}", sources.render_diagnostic(&diagnostic)),
+                        None => println!("{}", token),
+                    }
+                }
+            },
+            ":load" => match std::fs::read_to_string(argument) {
+                Ok(source) => self.repl.eval(argument, &source),
+                Err(e) => eprintln!("Failed to read file {}: {}", argument, e),
+            },
+            ":reset" => self.repl.reset(),
+            ":help" => println!("{}", HELP),
+            ":quit" => return false,
+            _ => eprintln!("Unknown command {}, try :help", command),
+        }
+        true
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{diagnostics::Diagnostic, error::LoxError};
+
+/// Identifies a file added to a `SourceMap`. The default id is the first
+/// file added, which is what `scan` and `compile` assume.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub usize);
+
+/// A named source text with the byte offset of each of its lines, so
+/// offsets can be turned into line and column numbers on demand.
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    name: String,
+    source: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        let source = source.into();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self {
+            name: name.into(),
+            source,
+            line_starts,
+        }
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+    /// 1-based line containing `offset`.
+    pub fn line(&self, offset: usize) -> usize {
+        let offset = self.clamp(offset);
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+    /// Text of the 1-based `line`, without its line ending.
+    pub fn line_text(&self, line: usize) -> &str {
+        let Some(&start) = self.line_starts.get(line.wrapping_sub(1)) else {
+            return "";
+        };
+        let end = self.line_starts.get(line).map_or(self.source.len(), |&next| next - 1);
+        self.source[start..end].trim_end_matches('\r')
+    }
+    /// 1-based line and column of `offset`, counting columns in UTF-8 bytes.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = self.clamp(offset);
+        let line = self.line(offset);
+        (line, offset - self.line_starts[line - 1] + 1)
+    }
+    /// 1-based line and column of `offset`, counting columns in UTF-16 code
+    /// units as editors speaking LSP do.
+    pub fn line_col_utf16(&self, offset: usize) -> (usize, usize) {
+        let offset = self.clamp(offset);
+        let line = self.line(offset);
+        let prefix = &self.source[self.line_starts[line - 1]..offset];
+        (line, prefix.encode_utf16().count() + 1)
+    }
+    /// Byte offset of a 1-based line and UTF-16 column, clamped to the line.
+    pub fn offset_utf16(&self, line: usize, column: usize) -> usize {
+        let Some(&start) = self.line_starts.get(line.wrapping_sub(1)) else {
+            return self.source.len();
+        };
+        let mut units = 0;
+        for (index, c) in self.line_text(line).char_indices() {
+            if units + 1 >= column {
+                return start + index;
+            }
+            units += c.len_utf16();
+        }
+        start + self.line_text(line).len()
+    }
+    /// `offset` moved inside the source and back onto a char boundary.
+    fn clamp(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+/// Owns every source file a session has seen, so spans from any of them
+/// can be rendered after the fact.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.files.push(SourceFile::new(name, source));
+        FileId(self.files.len() - 1)
+    }
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file.0)
+    }
+    /// Formats every diagnostic of `error` against the file its span is in.
+    pub fn render(&self, error: &LoxError) -> String {
+        match error {
+            LoxError::Io(_) | LoxError::Other(_) => format!("{}\n", error),
+            _ => error.diagnostics().iter().map(|diagnostic| self.render_diagnostic(diagnostic)).collect(),
+        }
+    }
+    /// Formats `diagnostic` against the file its span is in.
+    pub fn render_diagnostic(&self, diagnostic: &Diagnostic) -> String {
+        match self.get(diagnostic.span.file) {
+            Some(file) => diagnostic.render_file(file),
+            None => format!("{}\n", diagnostic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceFile;
+
+    /// Bytes: `ab\n` at 0..3, `cé\r\n` at 3..8, `x😀y\n` at 8..15, then an
+    /// empty last line at 15.
+    fn file() -> SourceFile {
+        SourceFile::new("test.lox", "ab\ncé\r\nx😀y\n")
+    }
+
+    #[test]
+    fn line() {
+        let file = file();
+        assert_eq!(file.line_count(), 4);
+        assert_eq!(file.line(0), 1);
+        assert_eq!(file.line(2), 1, "the newline belongs to the line it ends");
+        assert_eq!(file.line(3), 2);
+        assert_eq!(file.line(7), 2);
+        assert_eq!(file.line(14), 3);
+        assert_eq!(file.line(15), 4);
+        assert_eq!(file.line(100), 4);
+    }
+
+    #[test]
+    fn line_text() {
+        let file = file();
+        assert_eq!(file.line_text(1), "ab");
+        assert_eq!(file.line_text(2), "cé");
+        assert_eq!(file.line_text(3), "x😀y");
+        assert_eq!(file.line_text(4), "");
+        assert_eq!(file.line_text(0), "");
+        assert_eq!(file.line_text(5), "");
+    }
+
+    #[test]
+    fn line_col() {
+        let file = file();
+        assert_eq!(file.line_col(0), (1, 1));
+        assert_eq!(file.line_col(2), (1, 3));
+        assert_eq!(file.line_col(6), (2, 4), "columns count the `\\r` of a CRLF");
+        assert_eq!(file.line_col(5), (2, 2), "inside `é` moves back to its start");
+        assert_eq!(file.line_col(13), (3, 6));
+        assert_eq!(file.line_col(15), (4, 1));
+        assert_eq!(file.line_col(100), (4, 1));
+    }
+
+    #[test]
+    fn line_col_utf16() {
+        let file = file();
+        assert_eq!(file.line_col_utf16(6), (2, 3));
+        assert_eq!(file.line_col_utf16(9), (3, 2));
+        assert_eq!(file.line_col_utf16(11), (3, 2), "inside `😀` moves back to its start");
+        assert_eq!(file.line_col_utf16(13), (3, 4), "`😀` is a surrogate pair");
+        assert_eq!(file.line_col_utf16(14), (3, 5));
+        assert_eq!(file.line_col_utf16(100), (4, 1));
+    }
+
+    #[test]
+    fn offset_utf16() {
+        let file = file();
+        assert_eq!(file.offset_utf16(1, 1), 0);
+        assert_eq!(file.offset_utf16(2, 3), 6);
+        assert_eq!(file.offset_utf16(2, 99), 6, "clamped before the CRLF");
+        assert_eq!(file.offset_utf16(3, 2), 9);
+        assert_eq!(file.offset_utf16(3, 3), 13, "inside a surrogate pair moves past it");
+        assert_eq!(file.offset_utf16(3, 4), 13);
+        assert_eq!(file.offset_utf16(3, 99), 14);
+        assert_eq!(file.offset_utf16(4, 1), 15);
+        assert_eq!(file.offset_utf16(0, 1), 15);
+        assert_eq!(file.offset_utf16(9, 1), 15);
+        for offset in [0, 2, 3, 4, 6, 8, 9, 13, 14, 15] {
+            let (line, column) = file.line_col_utf16(offset);
+            assert_eq!(file.offset_utf16(line, column), offset);
+        }
+    }
+}
@@ -0,0 +1,550 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Display, io::Write, rc::Rc};
+
+use crate::{
+    chunk::{Chunk, OpCode},
+    compiler::Compiler,
+    diagnostics::{self, Diagnostic},
+    environment::{Environment, Scope},
+    error::LoxError,
+    interpreter::{define_builtins, define_native, runtime_error, Class, Closure, Instance, MAX_CALL_DEPTH},
+    parser::{Litteral, Statement},
+};
+
+struct Frame {
+    chunk: Rc<Chunk>,
+    ip: usize,
+    function: Option<Rc<Closure>>,
+    /// Scope to restore once this frame returns.
+    previous: Scope,
+    base: usize,
+}
+
+/// Stack-based virtual machine executing chunks produced by `Compiler`.
+/// Runtime values and scopes are shared with the tree-walking interpreter,
+/// so both backends produce the same output.
+pub struct Vm {
+    stack: Vec<Litteral>,
+    frames: Vec<Frame>,
+    environment: Scope,
+    global: Scope,
+    output: Box<dyn Write>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(std::io::stdout()))
+    }
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let global = Environment::new().scope();
+        define_builtins(&global);
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            environment: global.clone(),
+            global,
+            output,
+        }
+    }
+    pub fn interpret(&mut self, statements: &[Statement]) -> Result<(), LoxError> {
+        let chunk = Compiler::compile(statements);
+        Ok(self.run(Rc::new(chunk))?)
+    }
+    /// Runs `chunk` to completion. After a runtime error the frames, value
+    /// stack and scope are put back as they were, so the VM can be reused.
+    pub fn run(&mut self, chunk: Rc<Chunk>) -> anyhow::Result<()> {
+        let (frames, base, environment) = (self.frames.len(), self.stack.len(), self.environment.clone());
+        self.frames.push(Frame {
+            chunk,
+            ip: 0,
+            function: None,
+            previous: environment.clone(),
+            base,
+        });
+        let result = self.execute();
+        if result.is_err() {
+            self.frames.truncate(frames);
+            self.stack.truncate(base);
+            self.environment = environment;
+        }
+        result
+    }
+    fn execute(&mut self) -> anyhow::Result<()> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            if frame.ip == frame.chunk.code.len() {
+                let frame = self.frames.pop().unwrap();
+                self.environment = frame.previous;
+                return Ok(());
+            }
+            let chunk = frame.chunk.clone();
+            let op = chunk.code[frame.ip].clone();
+            frame.ip += 1;
+            match op {
+                OpCode::Constant(index) => {
+                    self.stack.push(chunk.constants[index].clone());
+                },
+                OpCode::Nil => self.stack.push(Litteral::Nil),
+                OpCode::Pop => {
+                    self.pop();
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(self.output, "{}", value)?;
+                },
+                OpCode::Define(name) => {
+                    let value = self.pop();
+                    self.environment.borrow_mut().define(chunk.name(name).clone(), value);
+                },
+                OpCode::Get(name, depth) => {
+                    let name = chunk.name(name);
+                    let value = match depth {
+                        Some(depth) => self.environment.borrow().get_at(depth, name),
+                        None => self.global.borrow().get(name),
+                    };
+                    match value {
+                        Ok(v) => self.stack.push(v),
+                        Err(e) => return Err(self.runtime_error(diagnostics::UNDEFINED_VARIABLE, e)),
+                    }
+                },
+                OpCode::Set(name, depth) => {
+                    let name = chunk.name(name).clone();
+                    let value = self.peek(0).clone();
+                    let assigned = match depth {
+                        Some(depth) => self.environment.borrow_mut().assign_at(depth, name, value),
+                        None => self.global.borrow_mut().assign(name, value),
+                    };
+                    if let Err(e) = assigned {
+                        return Err(self.runtime_error(diagnostics::UNDEFINED_VARIABLE, e));
+                    }
+                },
+                OpCode::Binary(operator) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    match operator.eval(left, right) {
+                        Ok(lit) => self.stack.push(lit),
+                        Err(e) => return Err(self.runtime_error(diagnostics::OPERAND_TYPE, e)),
+                    }
+                },
+                OpCode::Unary(operator) => {
+                    let value = self.pop();
+                    match operator.eval(value) {
+                        Ok(lit) => self.stack.push(lit),
+                        Err(e) => return Err(self.runtime_error(diagnostics::OPERAND_TYPE, e)),
+                    }
+                },
+                OpCode::Jump(target) => self.frame().ip = target,
+                OpCode::JumpIfFalse(target) => {
+                    if !self.peek(0).is_truthy() {
+                        self.frame().ip = target;
+                    }
+                },
+                OpCode::JumpIfTrue(target) => {
+                    if self.peek(0).is_truthy() {
+                        self.frame().ip = target;
+                    }
+                },
+                OpCode::PushScope => {
+                    self.environment = Environment::with_parent(self.environment.clone()).scope();
+                },
+                OpCode::PopScope => {
+                    let parent = self.environment.borrow().parent.clone();
+                    self.environment = parent.expect("popped the global scope");
+                },
+                OpCode::Closure(index) => {
+                    let function = &chunk.functions[index];
+                    let closure = Closure {
+                        declaration: function.declaration.clone(),
+                        closure: self.environment.clone(),
+                        is_initializer: false,
+                        chunk: Some(function.chunk.clone()),
+                    };
+                    self.stack.push(Litteral::Function(Rc::new(closure)));
+                },
+                OpCode::Call(argc) => {
+                    let callee = self.peek(argc).clone();
+                    let arity = match &callee {
+                        Litteral::Function(function) => function.arity(),
+                        Litteral::Native(native) => native.arity,
+                        Litteral::Class(class) => class.arity(),
+                        _ => return Err(self.runtime_error(
+                            diagnostics::NOT_CALLABLE,
+                            "Can only call functions and classes."
+                        )),
+                    };
+                    if argc != arity {
+                        return Err(self.runtime_error(
+                            diagnostics::ARITY_MISMATCH,
+                            format!("Expected {} arguments but got {}.", arity, argc)
+                        ));
+                    }
+                    match callee {
+                        Litteral::Function(function) => self.call(function, argc)?,
+                        Litteral::Native(native) => {
+                            let args = self.stack.split_off(self.stack.len() - argc);
+                            self.stack.pop();
+                            match (native.function)(&args) {
+                                Ok(value) => self.stack.push(value),
+                                Err(e) => return Err(self.runtime_error(diagnostics::NATIVE_ERROR, e)),
+                            }
+                        },
+                        Litteral::Class(class) => {
+                            let instance = Rc::new(RefCell::new(Instance {
+                                class: class.clone(),
+                                fields: HashMap::new(),
+                            }));
+                            let len = self.stack.len();
+                            self.stack[len - argc - 1] = Litteral::Instance(instance.clone());
+                            match class.find_method("init") {
+                                Some(init) => self.call(Rc::new(init.bind(instance)), argc)?,
+                                None => self.stack.truncate(len - argc),
+                            }
+                        },
+                        _ => unreachable!(),
+                    }
+                },
+                OpCode::Return => {
+                    let mut value = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    if let Some(function) = &frame.function {
+                        if function.is_initializer {
+                            value = function.closure.borrow().get(&"this".to_string())?;
+                        }
+                    }
+                    self.stack.truncate(frame.base);
+                    self.environment = frame.previous;
+                    self.stack.push(value);
+                },
+                OpCode::Class(index) => {
+                    let class = &chunk.classes[index];
+                    let superclass = match &class.superclass {
+                        Some((parent, _, position)) if *parent == class.name => {
+                            return Err(runtime_error(
+                                diagnostics::INVALID_SUPERCLASS,
+                                "A class can't inherit from itself.",
+                                position
+                            ));
+                        },
+                        Some((parent, depth, position)) => {
+                            let value = match depth {
+                                Some(depth) => self.environment.borrow().get_at(*depth, parent),
+                                None => self.global.borrow().get(parent),
+                            };
+                            match value {
+                                Ok(Litteral::Class(class)) => Some(class),
+                                Ok(_) => return Err(runtime_error(
+                                    diagnostics::INVALID_SUPERCLASS,
+                                    "Superclass must be a class.",
+                                    position
+                                )),
+                                Err(e) => return Err(runtime_error(diagnostics::UNDEFINED_VARIABLE, e, position)),
+                            }
+                        },
+                        None => None,
+                    };
+                    let scope = match &superclass {
+                        Some(parent) => {
+                            let mut scope = Environment::with_parent(self.environment.clone());
+                            scope.define("super".to_string(), Litteral::Class(parent.clone()));
+                            scope.scope()
+                        },
+                        None => self.environment.clone(),
+                    };
+                    let methods = class.methods.iter().map(|&index| {
+                        let function = &chunk.functions[index];
+                        let closure = Closure {
+                            declaration: function.declaration.clone(),
+                            closure: scope.clone(),
+                            is_initializer: function.declaration.name == "init",
+                            chunk: Some(function.chunk.clone()),
+                        };
+                        (closure.declaration.name.clone(), Rc::new(closure))
+                    }).collect();
+                    let class = Class { name: class.name.clone(), superclass, methods };
+                    self.stack.push(Litteral::Class(Rc::new(class)));
+                },
+                OpCode::GetProperty(name) => {
+                    let name = chunk.name(name);
+                    let Litteral::Instance(instance) = self.pop() else {
+                        return Err(self.runtime_error(diagnostics::NOT_AN_INSTANCE, "Only instances have properties."));
+                    };
+                    match Instance::get(&instance, name) {
+                        Some(value) => self.stack.push(value),
+                        None => return Err(self.runtime_error(
+                            diagnostics::UNDEFINED_PROPERTY,
+                            format!("Undefined property '{}'.", name)
+                        )),
+                    }
+                },
+                OpCode::SetProperty(name) => {
+                    let name = chunk.name(name).clone();
+                    let value = self.pop();
+                    let Litteral::Instance(instance) = self.pop() else {
+                        return Err(self.runtime_error(diagnostics::NOT_AN_INSTANCE, "Only instances have fields."));
+                    };
+                    instance.borrow_mut().fields.insert(name, value.clone());
+                    self.stack.push(value);
+                },
+                OpCode::This => {
+                    let this = self.environment.borrow().get(&"this".to_string());
+                    match this {
+                        Ok(v) => self.stack.push(v),
+                        Err(e) => return Err(self.runtime_error(diagnostics::INVALID_THIS, e)),
+                    }
+                },
+                OpCode::GetSuper(method) => {
+                    let method = chunk.name(method);
+                    let superclass = self.environment.borrow().get(&"super".to_string());
+                    let this = self.environment.borrow().get(&"this".to_string());
+                    let (Ok(Litteral::Class(superclass)), Ok(Litteral::Instance(instance))) = (superclass, this) else {
+                        return Err(self.runtime_error(
+                            diagnostics::INVALID_SUPER,
+                            "Can't use 'super' outside of a subclass method."
+                        ));
+                    };
+                    match superclass.find_method(method) {
+                        Some(found) => self.stack.push(Litteral::Function(Rc::new(found.bind(instance)))),
+                        None => return Err(self.runtime_error(
+                            diagnostics::UNDEFINED_PROPERTY,
+                            format!("Undefined property '{}'.", method)
+                        )),
+                    }
+                },
+                OpCode::Interpolate(count) => {
+                    let parts = self.stack.split_off(self.stack.len() - count);
+                    let joined: String = parts.iter().map(Litteral::to_string).collect();
+                    self.stack.push(Litteral::String(joined));
+                },
+            }
+        }
+    }
+    /// Enters `function`, whose callee and `argc` arguments are on top of the stack.
+    fn call(&mut self, function: Rc<Closure>, argc: usize) -> anyhow::Result<()> {
+        // The bottom frame runs the script itself rather than a call.
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(self.runtime_error(diagnostics::STACK_OVERFLOW, "Stack overflow."));
+        }
+        let Some(chunk) = function.chunk.clone() else {
+            return Err(self.runtime_error(diagnostics::NOT_CALLABLE, "Can only call functions and classes."));
+        };
+        let mut scope = Environment::with_parent(function.closure.clone());
+        let args = self.stack.split_off(self.stack.len() - argc);
+        for (param, arg) in function.declaration.params.iter().zip(args) {
+            scope.define(param.clone(), arg);
+        }
+        self.stack.pop();
+        let previous = std::mem::replace(&mut self.environment, scope.scope());
+        self.frames.push(Frame {
+            chunk,
+            ip: 0,
+            function: Some(function),
+            previous,
+            base: self.stack.len(),
+        });
+        Ok(())
+    }
+    /// Registers `function` as a global callable from Lox with `arity` arguments.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Litteral]) -> anyhow::Result<Litteral> + 'static
+    ) {
+        define_native(&self.global, name, arity, function);
+    }
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+    fn pop(&mut self) -> Litteral {
+        self.stack.pop().expect("value stack underflow")
+    }
+    fn peek(&self, distance: usize) -> &Litteral {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+    /// Reports an error at the instruction that was just executed.
+    fn runtime_error(&self, code: &'static str, message: impl Display) -> anyhow::Error {
+        let frame = self.frames.last().unwrap();
+        let position = frame.chunk.positions[frame.ip - 1].clone();
+        Diagnostic::error(code, message.to_string(), position).into()
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vm;
+    use crate::{compile, compile_file, interpreter::Interpreter, lox::Capture, source_map::SourceMap};
+
+    /// Printed output of `source`, followed by its rendered error if it
+    /// fails, on the tree-walker and then on the VM.
+    fn run_both(source: &str) -> (String, String) {
+        let mut sources = SourceMap::new();
+        let statements = compile_file(sources.add("", source), source).unwrap();
+        let capture = Capture::default();
+        let result = Interpreter::with_output(Box::new(capture.clone())).interpret(statements.clone());
+        let tree = capture.take() + &result.err().map(|e| sources.render(&e)).unwrap_or_default();
+        let result = Vm::with_output(Box::new(capture.clone())).interpret(&statements);
+        let vm = capture.take() + &result.err().map(|e| sources.render(&e)).unwrap_or_default();
+        (tree, vm)
+    }
+
+    /// Checks both backends agree on `source` and that it prints `expected`.
+    fn parity(source: &str, expected: &str) {
+        let (tree, vm) = run_both(source);
+        assert_eq!(tree, vm, "backends disagree on:\n{}", source);
+        assert!(tree.starts_with(expected), "unexpected output for:\n{}\n{}", source, tree);
+    }
+
+    #[test]
+    fn closures() {
+        parity("
+            fun counter() {
+                var count = 0;
+                fun increment() { count = count + 1; return count; }
+                return increment;
+            }
+            var a = counter();
+            var b = counter();
+            print a(); print a(); print b();
+        ", "1\n2\n1\n");
+        parity("
+            var x = \"global\";
+            {
+                fun show() { print x; }
+                show();
+                var x = \"local\";
+                show();
+            }
+        ", "global\nglobal\n");
+        parity("
+            var fs = \"\";
+            for (var i = 0; i < 3; i = i + 1) { fun f() { return i; } fs = fs + \"${f()}\"; }
+            print fs;
+        ", "012\n");
+    }
+
+    #[test]
+    fn classes() {
+        parity("
+            class Point {
+                init(x, y) { this.x = x; this.y = y; }
+                sum() { return this.x + this.y; }
+            }
+            var p = Point(1, 2);
+            print p.sum();
+            var sum = p.sum;
+            p.x = 10;
+            print sum();
+            print p;
+            print Point;
+            print p.init(3, 4).y;
+        ", "3\n12\nPoint instance\nPoint\n4\n");
+    }
+
+    #[test]
+    fn superclass_calls() {
+        parity("
+            class A {
+                name() { return \"A\"; }
+                greet() { return \"hi from \" + this.name(); }
+            }
+            class B < A {
+                name() { return \"B\"; }
+                greet() { return super.greet() + \" via B\"; }
+            }
+            class C < B {}
+            print C().greet();
+            print B().greet();
+        ", "hi from B via B\nhi from B via B\n");
+    }
+
+    #[test]
+    fn logical_operators() {
+        parity("
+            class A {}
+            fun f() { return \"called\"; }
+            print nil or A();
+            print false or f;
+            var g = nil or f;
+            print g();
+            print A() and f;
+            print nil or nil;
+            print nil and 1;
+            print false or false;
+            print 1 and nil;
+            print \"a\" or f;
+        ", "A instance\n<fn f>\ncalled\n<fn f>\nnil\nnil\nfalse\nnil\na\n");
+    }
+
+    #[test]
+    fn equality() {
+        parity("
+            print nil == nil;
+            print nil == false;
+            print nil != 0;
+            print \"1\" == 1;
+            fun f() {}
+            var g = f;
+            print f == g;
+            print f == clock;
+            class A {}
+            var a = A();
+            print a == a;
+            print a == A();
+            print A == A;
+        ", "true\nfalse\ntrue\nfalse\ntrue\nfalse\ntrue\nfalse\ntrue\n");
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let programs = [
+            "print 1; print \"a\" - 1;",
+            "print -\"a\";",
+            "print undefined;",
+            "undefined = 1;",
+            "fun f(a) {} f();",
+            "\"not a function\"();",
+            "var a = 1; print a.field;",
+            "var a = 1; a.field = 2;",
+            "class A {} print A().missing;",
+            "class A < A {}",
+            "var B = 1; class A < B {}",
+            "fun f(x) { return x * 2; } print f(1); print f(nil);",
+            "class A { init() { this.x = 1 + nil; } } A();",
+        ];
+        for source in programs {
+            let (tree, vm) = run_both(source);
+            assert!(tree.contains("error["), "no error for:\n{}", source);
+            assert_eq!(tree, vm, "backends disagree on:\n{}", source);
+        }
+    }
+
+    #[test]
+    fn stack_overflow() {
+        // The tree-walker needs a deep host stack to reach the call limit.
+        let runner = std::thread::Builder::new().stack_size(256 * 1024 * 1024).spawn(|| {
+            run_both("fun f(n) { return f(n + 1); } f(0);")
+        });
+        let (tree, vm) = runner.unwrap().join().unwrap();
+        assert!(tree.contains("Stack overflow."));
+        assert_eq!(tree, vm);
+    }
+
+    #[test]
+    fn reusable_after_runtime_error() {
+        let capture = Capture::default();
+        let mut vm = Vm::with_output(Box::new(capture.clone()));
+        let failing = compile("var a = 1; fun f(x) { { var y = x; return y + nil; } } print f(1);").unwrap();
+        assert!(vm.interpret(&failing).is_err());
+        assert!(vm.frames.is_empty());
+        assert!(vm.stack.is_empty());
+        assert!(vm.environment.borrow().parent.is_none());
+        let next = compile("fun g(x) { return x + a; } print g(2);").unwrap();
+        vm.interpret(&next).unwrap();
+        assert_eq!(capture.take(), "3\n");
+    }
+}